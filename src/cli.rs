@@ -1,7 +1,14 @@
-use crate::debugger::{Disassembler, Subordinate};
+use crate::debugger::{
+    signal, syscall, Access, BreakpointKind, Disassembler, Expr, ForkMode, Frame, IoMode, LineInfo,
+    Location, Redirects, Registers, SignalHandling, StopReason, Subordinate, Variable,
+};
 use crate::result::Result;
+use crate::sys;
+use libc::STDIN_FILENO;
 use rustyline::error::ReadlineError;
 use rustyline::Editor;
+use std::io::{self, Write};
+use std::path::PathBuf;
 
 pub struct Cli {
     subordinate: Subordinate,
@@ -13,23 +20,48 @@ impl Cli {
     }
 
     pub fn start(&mut self) -> Result<()> {
+        sys::catch_interrupts()?;
+        // Our stdin only goes to the program if there's someone typing.
+        self.subordinate
+            .connect_output(Box::new(io::stdout()), sys::isatty(STDIN_FILENO));
         let mut rl = Editor::<()>::new();
         loop {
+            print_output(&mut self.subordinate)?;
             let readline = rl.readline("> ");
             match readline {
                 Ok(line) => {
-                    execute_command(&mut self.subordinate, line.split_whitespace().collect())?;
-                    if let Some(exit_status) = self.subordinate.exit_status() {
-                        println!("debugged process exited with status: {}", exit_status);
+                    let cmd: Vec<&str> = line.split_whitespace().collect();
+                    if let ["q"] | ["quit"] = cmd.as_slice() {
+                        break;
+                    }
+                    if let Err(e) = execute_command(&mut self.subordinate, cmd) {
+                        println!("{}", e);
+                    }
+                    if self.subordinate.is_detached() {
                         break;
                     }
                 }
-                Err(ReadlineError::Interrupted) => break,
+                // Like a shell, Ctrl-C just throws away the line.
+                Err(ReadlineError::Interrupted) => continue,
                 Err(ReadlineError::Eof) => break,
                 Err(err) => return Err(err.into()),
             }
         }
 
+        // A process we attached to was running fine before we came along,
+        // so leave it running rather than stopped with int3s in it. One we
+        // started goes away with us.
+        if !self.subordinate.is_detached() && self.subordinate.is_alive() {
+            if self.subordinate.is_attached() {
+                self.subordinate.detach()?;
+            } else {
+                self.subordinate.kill()?;
+            }
+        }
+        if !self.subordinate.is_detached() {
+            self.subordinate.release_held()?;
+        }
+
         Ok(())
     }
 }
@@ -38,10 +70,102 @@ fn execute_command(subordinate: &mut Subordinate, cmd: Vec<&str>) -> Result<()>
     match cmd.as_slice() {
         ["regs"] | ["registers"] => print_registers(subordinate)?,
         ["r", name] | ["reg", name] | ["register", name] => print_register(subordinate, name)?,
-        ["si"] | ["stepi"] => subordinate.step()?,
-        ["c"] | ["cont"] => subordinate.cont()?,
+        ["si"] | ["stepi"] => {
+            let reason = subordinate.stepi()?;
+            print_stop(subordinate, &reason)?;
+        }
+        ["s"] | ["step"] => {
+            let reason = subordinate.step()?;
+            print_stop(subordinate, &reason)?;
+        }
+        ["ni"] | ["nexti"] => {
+            let reason = subordinate.nexti()?;
+            print_stop(subordinate, &reason)?;
+        }
+        ["n"] | ["next"] => {
+            let reason = subordinate.next()?;
+            print_stop(subordinate, &reason)?;
+        }
+        ["fin"] | ["finish"] => finish(subordinate)?,
+        ["u"] | ["until"] => {
+            let reason = subordinate.until()?;
+            print_stop(subordinate, &reason)?;
+        }
+        ["u", location] | ["until", location] => {
+            subordinate.until_location(&Location::parse(location)?)?;
+            print_stop(subordinate, &subordinate.stop_reason())?;
+        }
+        ["advance", location] => {
+            subordinate.advance(&Location::parse(location)?)?;
+            print_stop(subordinate, &subordinate.stop_reason())?;
+        }
+        ["c"] | ["cont"] => {
+            let reason = subordinate.cont()?;
+            print_stop(subordinate, &reason)?;
+        }
+        ["run", args @ ..] => {
+            let args = match args {
+                [] => None,
+                args => {
+                    let (args, redirects) = parse_redirects(args)?;
+                    subordinate.set_redirects(redirects);
+                    Some(args)
+                }
+            };
+            subordinate.restart(args)?;
+            let mut cmd = subordinate.cmd().join(" ");
+            if let Some(path) = &subordinate.redirects().stdin {
+                cmd += &format!(" < {}", path.display());
+            }
+            if let Some(path) = &subordinate.redirects().stdout {
+                cmd += &format!(" > {}", path.display());
+            }
+            println!("starting program: {}", cmd);
+            let reason = subordinate.cont()?;
+            print_stop(subordinate, &reason)?;
+        }
+        ["restart"] => {
+            subordinate.restart(None)?;
+            println!("restarted program: {}", subordinate.cmd().join(" "));
+            print_stop(subordinate, &subordinate.stop_reason())?;
+        }
+        ["kill"] => {
+            subordinate.kill()?;
+            println!("killed process {}", subordinate.pid());
+        }
+        ["up"] | ["up", _] => {
+            let n = match cmd.get(1) {
+                Some(n) => n.parse()?,
+                None => 1,
+            };
+            let level = subordinate.selected_frame() + n;
+            if subordinate.backtrace()?.len() <= level {
+                println!("initial frame selected; you cannot go up");
+            } else {
+                select_frame(subordinate, level)?;
+            }
+        }
+        ["down"] | ["down", _] => {
+            let n = match cmd.get(1) {
+                Some(n) => n.parse()?,
+                None => 1,
+            };
+            match subordinate.selected_frame().checked_sub(n) {
+                Some(level) => select_frame(subordinate, level)?,
+                None => println!("bottom (innermost) frame selected; you cannot go down"),
+            }
+        }
+        ["f"] | ["frame"] => select_frame(subordinate, subordinate.selected_frame())?,
+        ["f", level] | ["frame", level] => select_frame(subordinate, level.parse()?)?,
+        ["info", "frame"] => print_frame_info(subordinate)?,
+        ["i", "args"] | ["info", "args"] => print_variables(subordinate.args()?, "no arguments."),
+        ["i", "locals"] | ["info", "locals"] => {
+            print_variables(subordinate.locals()?, "no locals.")
+        }
+        ["bt"] | ["backtrace"] => print_backtrace(subordinate, None)?,
+        ["bt", limit] | ["backtrace", limit] => print_backtrace(subordinate, Some(limit.parse()?))?,
         ["d"] | ["disas"] => {
-            let rip = subordinate.registers().rip;
+            let rip = subordinate.frame()?.pc as u64;
             let bytes = subordinate.read_bytes(rip as usize, 64)?;
             let disassembly = Disassembler::new().disassemble(rip, &bytes)?;
             println!("{}", disassembly);
@@ -59,11 +183,18 @@ fn execute_command(subordinate: &mut Subordinate, cmd: Vec<&str>) -> Result<()>
                 }
             };
         }
+        ["l"] | ["list"] => {
+            let frame = subordinate.frame()?;
+            match &frame.line {
+                Some(line) => print_lines(subordinate, line, 5)?,
+                None => println!("no line information for 0x{:x}", frame.pc),
+            }
+        }
         ["l", sym] | ["list", sym] => {
             let debug_info = subordinate.debug_info();
             let lines = subordinate
                 .symbol(sym)
-                .and_then(|symbol| debug_info.line_info(symbol.value as usize))
+                .and_then(|symbol| subordinate.line_info(symbol.value as usize))
                 .and_then(|line_info| debug_info.lines(&line_info.path));
 
             if let Some(lines) = lines {
@@ -74,57 +205,624 @@ fn execute_command(subordinate: &mut Subordinate, cmd: Vec<&str>) -> Result<()>
         }
         ["syms"] | ["symbols"] => print_symbols(subordinate)?,
         ["sym", name] | ["symbol", name] => print_symbol(subordinate, name)?,
-        ["b", addr] | ["break", addr] => set_breakpoint(subordinate, addr)?,
+        ["b", addr] | ["break", addr] => set_breakpoint(subordinate, addr, None, false)?,
+        ["b", addr, "if", cond @ ..] | ["break", addr, "if", cond @ ..] => {
+            let condition = Expr::parse(&cond.join(" "))?;
+            set_breakpoint(subordinate, addr, Some(condition), false)?
+        }
+        ["tbreak", addr] => set_breakpoint(subordinate, addr, None, true)?,
+        ["tbreak", addr, "if", cond @ ..] => {
+            let condition = Expr::parse(&cond.join(" "))?;
+            set_breakpoint(subordinate, addr, Some(condition), true)?
+        }
+        ["hbreak", location] => {
+            let ids = subordinate.hardware_breakpoint(&Location::parse(location)?)?;
+            print_new_breakpoints(subordinate, &ids);
+        }
+        ["catch", "syscall", syscalls @ ..] => {
+            let mut numbers = Vec::new();
+            for name in syscalls {
+                match syscall::number(name).or_else(|| name.parse().ok()) {
+                    Some(number) => numbers.push(number),
+                    None => return Err(format!("unknown syscall `{}`", name).into()),
+                }
+            }
+            let id = subordinate.catch_syscalls(numbers)?;
+            print_new_breakpoints(subordinate, &[id]);
+        }
+        ["watch", addr, len] => set_watchpoint(subordinate, addr, len, Access::Write)?,
+        ["rwatch", addr, len] => set_watchpoint(subordinate, addr, len, Access::Read)?,
+        ["awatch", addr, len] => set_watchpoint(subordinate, addr, len, Access::ReadWrite)?,
+        ["ignore", id, count] => {
+            let id = id.parse()?;
+            let count = count.parse()?;
+            subordinate.ignore_breakpoint(id, count)?;
+            println!("will ignore next {} crossings of breakpoint {}", count, id);
+        }
+        ["condition", id] => subordinate.set_breakpoint_condition(id.parse()?, None)?,
+        ["condition", id, cond @ ..] => {
+            let condition = Expr::parse(&cond.join(" "))?;
+            subordinate.set_breakpoint_condition(id.parse()?, Some(condition))?
+        }
+        ["p", expr @ ..] | ["print", expr @ ..] if !expr.is_empty() => {
+            let value = Expr::parse(&expr.join(" "))?.eval(subordinate)?;
+            println!("0x{:x} ({})", value, value);
+        }
+        ["i", "b"] | ["info", "b"] | ["info", "breakpoints"] => print_breakpoints(subordinate)?,
+        ["i", "threads"] | ["info", "threads"] => print_threads(subordinate),
+        ["i", "signals"] | ["info", "signals"] => print_signals(subordinate, 1..=signal::NSIG)?,
+        ["i", "signals", name] | ["info", "signals", name] | ["handle", name] => {
+            let signal = parse_signal(name)?;
+            print_signals(subordinate, signal..=signal)?
+        }
+        ["handle", name, actions @ ..] => {
+            let signal = parse_signal(name)?;
+            let mut handling = subordinate.signal_handling(signal)?;
+            for action in actions {
+                match *action {
+                    // Stopping without saying why would be confusing.
+                    "stop" => {
+                        handling.stop = true;
+                        handling.print = true;
+                    }
+                    "nostop" => handling.stop = false,
+                    "print" => handling.print = true,
+                    "noprint" => {
+                        handling.print = false;
+                        handling.stop = false;
+                    }
+                    "pass" => handling.pass = true,
+                    "nopass" => handling.pass = false,
+                    other => return Err(format!("unknown signal action `{}`", other).into()),
+                }
+            }
+            subordinate.set_signal_handling(signal, handling)?;
+            print_signals(subordinate, signal..=signal)?
+        }
+        ["thread"] => print_threads(subordinate),
+        ["thread", id] => {
+            subordinate.select_thread(id.parse()?)?;
+            let rip = subordinate.registers().rip as usize;
+            println!(
+                "switching to thread {}, {}",
+                id,
+                describe_address(subordinate, rip)
+            );
+            print_source_line(subordinate)?;
+        }
+        ["delete", id] => subordinate.delete_breakpoint(id.parse()?)?,
+        ["disable", id] => subordinate.disable_breakpoint(id.parse()?)?,
+        ["enable", id] => subordinate.enable_breakpoint(id.parse()?)?,
+        ["set", "follow-fork-mode", "parent"] => subordinate.set_follow_fork_mode(ForkMode::Parent),
+        ["set", "follow-fork-mode", "child"] => subordinate.set_follow_fork_mode(ForkMode::Child),
+        ["set", "detach-on-fork", "on"] => subordinate.set_detach_on_fork(true),
+        ["set", "detach-on-fork", "off"] => subordinate.set_detach_on_fork(false),
+        ["show", "follow-fork-mode"] => {
+            let mode = match subordinate.follow_fork_mode() {
+                ForkMode::Parent => "parent",
+                ForkMode::Child => "child",
+            };
+            println!("follow-fork-mode is {}", mode);
+        }
+        ["set", "env", var @ ..] | ["set", "environment", var @ ..] if !var.is_empty() => {
+            // Any of NAME VALUE, NAME=VALUE or NAME = VALUE.
+            let var = var.join(" ");
+            let (name, value) = match var.find(|c: char| c == '=' || c.is_whitespace()) {
+                Some(i) => (&var[..i], var[i + 1..].trim_start_matches(&[' ', '='][..])),
+                None => (var.as_str(), ""),
+            };
+            subordinate.launch_mut().set_env(name, value);
+        }
+        ["unset", "env"] | ["unset", "environment"] => subordinate.launch_mut().clear_env(),
+        ["unset", "env", name] | ["unset", "environment", name] => {
+            subordinate.launch_mut().unset_env(name)
+        }
+        ["show", "env"] | ["show", "environment"] => {
+            for (name, value) in subordinate.launch().environment() {
+                println!("{}={}", name.to_string_lossy(), value.to_string_lossy());
+            }
+        }
+        ["show", "env", name] | ["show", "environment", name] => {
+            match subordinate
+                .launch()
+                .environment()
+                .into_iter()
+                .find(|(n, _)| n == name)
+            {
+                Some((_, value)) => println!("{}={}", name, value.to_string_lossy()),
+                None => println!("environment variable `{}` not defined", name),
+            }
+        }
+        ["set", "cwd", path] => subordinate.launch_mut().set_cwd(PathBuf::from(path))?,
+        ["show", "cwd"] => match subordinate.launch().cwd() {
+            Some(cwd) => println!("cwd is {}", cwd.display()),
+            None => println!("cwd is not set, the program starts where we are"),
+        },
+        ["set", "disable-randomization", "on"] => {
+            subordinate.launch_mut().set_disable_randomization(true)
+        }
+        ["set", "disable-randomization", "off"] => {
+            subordinate.launch_mut().set_disable_randomization(false)
+        }
+        ["show", "disable-randomization"] => {
+            let disable = if subordinate.launch().disable_randomization() {
+                "on"
+            } else {
+                "off"
+            };
+            println!("disable-randomization is {}", disable);
+        }
+        ["set", "inferior-io", mode] => {
+            let mode = match *mode {
+                "pty" => IoMode::Pty,
+                "pipe" => IoMode::Pipe,
+                "inherit" => IoMode::Inherit,
+                _ => return Err(format!("unknown mode `{}`", mode).into()),
+            };
+            subordinate.set_io_mode(mode);
+        }
+        ["show", "inferior-io"] => {
+            let mode = match subordinate.io_mode() {
+                IoMode::Pty => "pty",
+                IoMode::Pipe => "pipe",
+                IoMode::Inherit => "inherit",
+            };
+            println!("inferior-io is {}", mode);
+        }
+        ["show", "detach-on-fork"] => {
+            let detach = if subordinate.detach_on_fork() {
+                "on"
+            } else {
+                "off"
+            };
+            println!("detach-on-fork is {}", detach);
+        }
+        ["detach"] => {
+            subordinate.detach()?;
+            println!("detached from process {}", subordinate.pid());
+        }
         other => println!("unknown command `{:?}`", other),
     };
 
     Ok(())
 }
 
-fn set_breakpoint(subordinate: &mut Subordinate, addr: &str) -> Result<()> {
-    if let Some(hex) = addr.strip_prefix("0x") {
-        if let Ok(addr) = usize::from_str_radix(hex, 16) {
-            return subordinate.breakpoint(addr);
+fn set_breakpoint(
+    subordinate: &mut Subordinate,
+    location: &str,
+    condition: Option<Expr>,
+    temporary: bool,
+) -> Result<()> {
+    let location = Location::parse(location)?;
+    let ids = if temporary {
+        subordinate.temporary_breakpoint(&location)?
+    } else {
+        subordinate.breakpoint(&location)?
+    };
+
+    for id in ids.iter() {
+        subordinate.set_breakpoint_condition(*id, condition.clone())?;
+    }
+
+    print_new_breakpoints(subordinate, &ids);
+    Ok(())
+}
+
+fn print_new_breakpoints(subordinate: &Subordinate, ids: &[usize]) {
+    for bp in subordinate
+        .breakpoints()
+        .iter()
+        .filter(|bp| ids.contains(&bp.id))
+    {
+        if bp.kind == BreakpointKind::Syscall {
+            println!("{} {} ({})", bp.kind.name(), bp.id, bp.location());
+        } else if bp.pending {
+            println!("{} {} ({}) pending", bp.kind.name(), bp.id, bp.spec);
+        } else {
+            println!(
+                "{} {} at 0x{:x}: {}",
+                bp.kind.name(),
+                bp.id,
+                bp.addr,
+                bp.location()
+            );
         }
     }
+}
 
-    let name = addr;
-    if let Some(symbol) = subordinate.symbol(name).map(|s| s.to_owned()) {
-        return subordinate.breakpoint(symbol.value as usize);
+fn print_source_line(subordinate: &Subordinate) -> Result<()> {
+    if subordinate.exit_status().is_some() {
+        return Ok(());
     }
 
-    Err(format!(
-        "couldn't set breakpoint on `{}`, not a known address or symbol",
-        addr
-    )
-    .into())
+    let rip = subordinate.registers().rip as usize;
+    if let Some(line_info) = subordinate.line_for_pc(rip) {
+        print_line(subordinate, line_info);
+    }
+    Ok(())
 }
 
-fn print_registers(subordinate: &mut Subordinate) -> Result<()> {
-    let regs = subordinate.registers();
+fn print_line(subordinate: &Subordinate, line_info: &LineInfo) {
+    let source = subordinate
+        .debug_info()
+        .lines(&line_info.path)
+        .and_then(|lines| {
+            let index = (line_info.line as usize).checked_sub(1)?;
+            lines.get(index).cloned()
+        });
+    match source {
+        Some(source) => println!("{}\t{}", line_info.line, source),
+        None => println!("{}:{}", line_info.path.display(), line_info.line),
+    }
+}
 
-    println!("rip: 0x{:x}", regs.rip);
-    println!("rsp: 0x{:x}", regs.rsp);
-    println!("rbp: 0x{:x}", regs.rbp);
-    println!("rax: 0x{:x}", regs.rax);
-    println!("rbx: 0x{:x}", regs.rbx);
-    println!("rcx: 0x{:x}", regs.rcx);
-    println!("rdx: 0x{:x}", regs.rdx);
-    println!("rdi: 0x{:x}", regs.rdi);
-    println!("rsi: 0x{:x}", regs.rsi);
+/// Prints the lines either side of a line, numbered.
+fn print_lines(subordinate: &Subordinate, line_info: &LineInfo, context: u64) -> Result<()> {
+    let lines = match subordinate.debug_info().lines(&line_info.path) {
+        Some(lines) => lines,
+        None => return Err(format!("can't read {}", line_info.path.display()).into()),
+    };
+    let first = line_info.line.saturating_sub(context).max(1);
+    let last = (line_info.line + context).min(lines.len() as u64);
+    for number in first..=last {
+        println!("{}\t{}", number, lines[number as usize - 1]);
+    }
+    Ok(())
+}
 
+fn print_threads(subordinate: &Subordinate) {
+    let current = subordinate.current_thread();
+    println!("  {:<4} {:<8} where", "id", "lwp");
+    for thread in subordinate.threads() {
+        let marker = if thread.id == current { "*" } else { " " };
+        let rip = thread.registers.rip as usize;
+        println!(
+            "{} {:<4} {:<8} {}",
+            marker,
+            thread.id,
+            thread.tid,
+            describe_address(subordinate, rip)
+        );
+    }
+}
+
+/// Describes an address as e.g. `0x401126 in main+4 at hello.c:5`, as far as
+/// we have the information to.
+fn describe_address(subordinate: &Subordinate, addr: usize) -> String {
+    let mut description = format!("0x{:x}", addr);
+    if let Some(symbol) = subordinate.symbol_for_addr(addr) {
+        let offset = addr - symbol.value as usize;
+        description.push_str(&format!(" in {}+{}", symbol.name, offset));
+    }
+    if let Some(line) = subordinate.line_for_pc(addr) {
+        description.push_str(&format!(" at {}:{}", line.path.display(), line.line));
+    }
+    description
+}
+
+/// Describes a frame as `#1  0x401136 in main+20 at main.c:12`, saying
+/// which library it's in if it isn't in the program.
+fn describe_frame(subordinate: &Subordinate, frame: &Frame) -> String {
+    let mut description = format!("#{:<2} 0x{:x}", frame.level, frame.pc);
+    if let Some((name, offset)) = &frame.function {
+        description.push_str(&format!(" in {}+{}", name, offset));
+    }
+    match (&frame.line, &frame.module) {
+        (Some(line), _) => {
+            description.push_str(&format!(" at {}:{}", line.path.display(), line.line))
+        }
+        (None, Some(module)) if !module.ends_with(&subordinate.cmd()[0]) => {
+            description.push_str(&format!(" from {}", module.display()))
+        }
+        _ => {}
+    }
+    description
+}
+
+fn select_frame(subordinate: &mut Subordinate, level: usize) -> Result<()> {
+    let frame = subordinate.select_frame(level)?;
+    println!("{}", describe_frame(subordinate, &frame));
+    if let Some(line) = &frame.line {
+        print_line(subordinate, line);
+    }
     Ok(())
 }
 
-fn print_register(subordinate: &mut Subordinate, name: &str) -> Result<()> {
-    match subordinate.registers().get(name) {
-        Some(value) => {
-            println!("{} 0x{:x}", name, value);
+/// Says where the selected frame is, where it keeps its caller's registers
+/// and which frames are either side of it.
+fn print_frame_info(subordinate: &Subordinate) -> Result<()> {
+    let level = subordinate.selected_frame();
+    let frames = subordinate.backtrace()?;
+    let frame = match frames.get(level) {
+        Some(frame) => frame,
+        None => return Err(format!("no frame at level {}", level).into()),
+    };
+
+    println!("stack level {}, frame at 0x{:x}:", level, frame.cfa);
+    let mut location = format!(" rip = 0x{:x}", frame.pc);
+    if let Some((name, offset)) = &frame.function {
+        location.push_str(&format!(" in {}+{}", name, offset));
+    }
+    if let Some(line) = &frame.line {
+        location.push_str(&format!(" ({}:{})", line.path.display(), line.line));
+    }
+    if let Some(caller) = frames.get(level + 1) {
+        location.push_str(&format!("; saved rip = 0x{:x}", caller.pc));
+    }
+    println!("{}", location);
+    if let Some(caller) = frames.get(level + 1) {
+        println!(" called by frame at 0x{:x}", caller.cfa);
+    }
+    if level > 0 {
+        println!(" caller of frame at 0x{:x}", frames[level - 1].cfa);
+    }
+
+    if !frame.saved.is_empty() {
+        let saved: Vec<String> = frame
+            .saved
+            .iter()
+            .map(|(register, addr)| {
+                let name = Registers::dwarf_name(*register).unwrap_or("?");
+                format!("{} at 0x{:x}", name, addr)
+            })
+            .collect();
+        println!(" saved registers:");
+        println!("  {}", saved.join(", "));
+    }
+    Ok(())
+}
+
+fn print_variables(variables: Vec<(Variable, Result<Vec<u8>>)>, none: &str) {
+    if variables.is_empty() {
+        println!("{}", none);
+    }
+    for (variable, value) in variables {
+        let value = match (value, &variable.ty) {
+            (Ok(bytes), Some(ty)) => ty.format(&bytes),
+            (Ok(bytes), None) => format!("{:?}", bytes),
+            (Err(e), _) => format!("<{}>", e),
+        };
+        println!("{} = {}", variable.name, value);
+    }
+}
+
+fn print_backtrace(subordinate: &Subordinate, limit: Option<usize>) -> Result<()> {
+    let frames = subordinate.backtrace()?;
+    let limit = limit.unwrap_or(frames.len());
+    for frame in frames.iter().take(limit) {
+        println!("{}", describe_frame(subordinate, frame));
+    }
+    if frames.len() > limit {
+        println!("(more stack frames follow...)");
+    }
+    Ok(())
+}
+
+fn finish(subordinate: &mut Subordinate) -> Result<()> {
+    let rip = subordinate.registers().rip as usize;
+    if let Some(symbol) = subordinate.symbol_for_addr(rip) {
+        println!("run till exit from {}", symbol.name);
+    }
+
+    let finished = subordinate.finish()?;
+    print_stop(subordinate, &subordinate.stop_reason())?;
+    if !finished {
+        return Ok(());
+    }
+
+    // We don't know the function's return type, so show both places a
+    // value could have been returned.
+    let rax = subordinate.registers().rax;
+    let xmm0 = subordinate.xmm(0)?;
+    println!("value returned in rax: 0x{:x} ({})", rax, rax as i64);
+    println!(
+        "value returned in xmm0: {} (double), {} (float)",
+        f64::from_bits(xmm0 as u64),
+        f32::from_bits(xmm0 as u32)
+    );
+    Ok(())
+}
+
+fn set_watchpoint(
+    subordinate: &mut Subordinate,
+    addr: &str,
+    len: &str,
+    access: Access,
+) -> Result<()> {
+    let addr = Expr::parse(addr)?.eval(subordinate)? as usize;
+    let id = subordinate.watchpoint(addr, len.parse()?, access)?;
+
+    if let Some(bp) = subordinate.breakpoints().iter().find(|bp| bp.id == id) {
+        println!(
+            "{} {} at 0x{:x}: {}",
+            bp.kind.name(),
+            id,
+            addr,
+            bp.location()
+        );
+    }
+    Ok(())
+}
+
+/// Says why the subordinate stopped and where, followed by the source line
+/// if we have it.
+fn print_stop(subordinate: &mut Subordinate, reason: &StopReason) -> Result<()> {
+    print_output(subordinate)?;
+    for stop in subordinate.take_noticed_signals() {
+        println!("received signal {}", stop);
+    }
+    if reason.is_exit() {
+        println!("debugged process {}", reason);
+        return Ok(());
+    }
+
+    let rip = subordinate.registers().rip as usize;
+    match reason {
+        StopReason::Step => println!("{}", describe_address(subordinate, rip)),
+        reason => println!("{} at {}", reason, describe_address(subordinate, rip)),
+    }
+
+    if let StopReason::Watchpoint(hits) = reason {
+        for hit in hits {
+            if hits.len() > 1 {
+                println!("watchpoint {}:", hit.id);
+            }
+            println!("old value: 0x{:x} ({})", hit.old, hit.old);
+            println!("new value: 0x{:x} ({})", hit.new, hit.new);
+        }
+        // They were all set off by the same instruction.
+        if let Some(addr) = hits.first().and_then(|hit| hit.instruction) {
+            let bytes = subordinate.read_bytes(addr, 16)?;
+            let disassembly = Disassembler::new().disassemble(addr as u64, &bytes)?;
+            if let Some(line) = disassembly.lines().next() {
+                println!("by instruction: {}", line);
+            }
+        }
+    }
+
+    print_source_line(subordinate)
+}
+
+/// Whatever the subordinate wrote that we haven't shown yet.
+fn print_output(subordinate: &mut Subordinate) -> Result<()> {
+    let output = subordinate.take_output();
+    if !output.is_empty() {
+        let mut stdout = io::stdout();
+        stdout.write_all(&output)?;
+        stdout.flush()?;
+    }
+    Ok(())
+}
+
+/// Splits `run` arguments into those for the program and `< file` and
+/// `> file` redirections.
+fn parse_redirects(args: &[&str]) -> Result<(Vec<String>, Redirects)> {
+    let mut redirects = Redirects::default();
+    let mut rest = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let (op, path) = match (arg.strip_prefix('<'), arg.strip_prefix('>')) {
+            (Some(path), _) => ("<", path),
+            (_, Some(path)) => (">", path),
+            _ => {
+                rest.push(arg.to_string());
+                continue;
+            }
+        };
+        let path = match path {
+            "" => match args.next() {
+                Some(path) => *path,
+                None => return Err(format!("missing file after `{}`", op).into()),
+            },
+            path => path,
+        };
+        if op == "<" {
+            redirects.stdin = Some(PathBuf::from(path));
+        } else {
+            redirects.stdout = Some(PathBuf::from(path));
         }
-        None => {
-            println!("couldn't find register with name \"{}\"", name);
+    }
+    Ok((rest, redirects))
+}
+
+fn parse_signal(name: &str) -> Result<i32> {
+    match signal::number(name) {
+        Some(signal) => Ok(signal),
+        None => Err(format!("unknown signal `{}`", name).into()),
+    }
+}
+
+fn print_signals(subordinate: &Subordinate, signals: std::ops::RangeInclusive<i32>) -> Result<()> {
+    let yes_no = |b| if b { "yes" } else { "no" };
+    println!(
+        "{:<10} {:<5} {:<5} {:<5} description",
+        "signal", "stop", "print", "pass"
+    );
+    for signal in signals {
+        let SignalHandling { stop, print, pass } = subordinate.signal_handling(signal)?;
+        println!(
+            "{:<10} {:<5} {:<5} {:<5} {}",
+            signal::name(signal),
+            yes_no(stop),
+            yes_no(print),
+            yes_no(pass),
+            signal::description(signal)
+        );
+    }
+    Ok(())
+}
+
+fn print_breakpoints(subordinate: &mut Subordinate) -> Result<()> {
+    if subordinate.breakpoints().is_empty() {
+        println!("no breakpoints");
+        return Ok(());
+    }
+
+    println!(
+        "{:<4} {:<15} {:<4} {:<4} {:<6} {:<18} what",
+        "num", "type", "disp", "enb", "hits", "address"
+    );
+    for bp in subordinate.breakpoints() {
+        let disp = if bp.temporary { "del" } else { "keep" };
+        let enabled = if bp.enabled { "y" } else { "n" };
+        let address = if bp.kind == BreakpointKind::Syscall {
+            String::new()
+        } else if bp.pending {
+            "<pending>".to_owned()
+        } else {
+            format!("0x{:x}", bp.addr)
+        };
+        println!(
+            "{:<4} {:<15} {:<4} {:<4} {:<6} {:<18} {}",
+            bp.id,
+            bp.kind.name(),
+            disp,
+            enabled,
+            bp.hit_count,
+            address,
+            if bp.pending {
+                bp.spec.to_string()
+            } else {
+                bp.location()
+            }
+        );
+        if !bp.pending && !matches!(bp.spec, Location::Address(_)) {
+            println!("        set at {}", bp.spec);
+        }
+        if let Some(ref condition) = bp.condition {
+            println!("        stop only if {}", condition);
+        }
+        if bp.ignore_count > 0 {
+            println!("        will ignore next {} crossings", bp.ignore_count);
+        }
+    }
+    Ok(())
+}
+
+fn print_registers(subordinate: &mut Subordinate) -> Result<()> {
+    let frame = subordinate.frame()?;
+
+    for name in &[
+        "rip", "rsp", "rbp", "rax", "rbx", "rcx", "rdx", "rdi", "rsi",
+    ] {
+        match frame.register(name) {
+            Some(value) => println!("{}: 0x{:x}", name, value),
+            None => println!("{}: <not saved>", name),
         }
     }
+
+    Ok(())
+}
+
+fn print_register(subordinate: &mut Subordinate, name: &str) -> Result<()> {
+    if !Registers::is_register(name) {
+        println!("couldn't find register with name \"{}\"", name);
+        return Ok(());
+    }
+    match subordinate.frame()?.register(name) {
+        Some(value) => println!("{} 0x{:x}", name, value),
+        None => println!("{} <not saved>", name),
+    }
     Ok(())
 }
 
@@ -149,3 +847,40 @@ fn print_symbol(subordinate: &mut Subordinate, name: &str) -> Result<()> {
     println!("couldn't find symbol with name \"{}\"", name);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn redirects(stdin: Option<&str>, stdout: Option<&str>) -> Redirects {
+        Redirects {
+            stdin: stdin.map(PathBuf::from),
+            stdout: stdout.map(PathBuf::from),
+        }
+    }
+
+    #[test]
+    fn redirections() {
+        let (args, found) = parse_redirects(&["a", "<", "in.txt", "b", ">out.txt"]).unwrap();
+        assert_eq!(args, vec!["a", "b"]);
+        assert_eq!(found, redirects(Some("in.txt"), Some("out.txt")));
+
+        let (args, found) = parse_redirects(&["<in.txt", ">", "out.txt"]).unwrap();
+        assert!(args.is_empty());
+        assert_eq!(found, redirects(Some("in.txt"), Some("out.txt")));
+
+        let (args, found) = parse_redirects(&["é", "a<b", ""]).unwrap();
+        assert_eq!(args, vec!["é", "a<b", ""]);
+        assert_eq!(found, Redirects::default());
+
+        // The last one wins, as in a shell.
+        let (_, found) = parse_redirects(&[">one", ">two"]).unwrap();
+        assert_eq!(found, redirects(None, Some("two")));
+    }
+
+    #[test]
+    fn missing_file() {
+        assert!(parse_redirects(&["a", "<"]).is_err());
+        assert!(parse_redirects(&[">"]).is_err());
+    }
+}
@@ -141,6 +141,31 @@ pub fn read(subordinate: &Subordinate) -> Result<Vec<Entry>> {
     Ok(auxv)
 }
 
+/// Reads the auxiliary vector from procfs, for processes we didn't start
+/// ourselves and so didn't catch before the stack moved on.
+pub fn read_proc(pid: i32) -> Result<Vec<Entry>> {
+    let mut auxv: Vec<Entry> = Vec::new();
+    let bytes = std::fs::read(format!("/proc/{}/auxv", pid))?;
+
+    for pair in bytes.chunks_exact(16) {
+        let mut aux_type = [0u8; 8];
+        let mut aux_val = [0u8; 8];
+        aux_type.clone_from_slice(&pair[0..8]);
+        aux_val.clone_from_slice(&pair[8..16]);
+
+        let aux_type = u64::from_le_bytes(aux_type);
+        if aux_type == 0 {
+            break;
+        }
+        auxv.push(Entry::new(
+            aux_type as usize,
+            u64::from_le_bytes(aux_val) as usize,
+        ));
+    }
+
+    Ok(auxv)
+}
+
 fn read_u64(subordinate: &Subordinate, addr: usize) -> Result<u64> {
     let mut buf = [0 as u8; 8];
     let bytes = subordinate.read_bytes(addr, 8)?;
@@ -0,0 +1,154 @@
+use crate::debugger::{dwarf::LineInfo, syscall, Expr, Location};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Access {
+    Write,
+    Read,
+    ReadWrite,
+}
+
+impl Access {
+    /// Whether an access that found `old` under a watchpoint and left `new`
+    /// is what it's watching for. Reads can only be trapped along with
+    /// writes, so as gdb does, a change of value is taken to be a write.
+    pub fn triggered_by(self, old: u64, new: u64) -> bool {
+        match self {
+            // Writing the same value back isn't interesting.
+            Access::Write => old != new,
+            Access::Read => old == new,
+            Access::ReadWrite => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BreakpointKind {
+    /// An int3 written over the instruction.
+    Software,
+    /// An execution breakpoint in one of the debug registers.
+    Hardware,
+    /// A data breakpoint in one of the debug registers, covering `len` bytes.
+    Watchpoint(Access, usize),
+    /// A catchpoint on entry to and exit from syscalls.
+    Syscall,
+}
+
+impl BreakpointKind {
+    pub fn uses_debug_register(self) -> bool {
+        match self {
+            BreakpointKind::Hardware | BreakpointKind::Watchpoint(_, _) => true,
+            BreakpointKind::Software | BreakpointKind::Syscall => false,
+        }
+    }
+
+    /// The RW and LEN fields for this kind of breakpoint, as they'd appear in
+    /// DR7 for debug register 0. Shift left by 4 for each register after.
+    pub fn dr7_bits(self) -> usize {
+        let (rw, len) = match self {
+            BreakpointKind::Software | BreakpointKind::Hardware | BreakpointKind::Syscall => {
+                (0b00, 1)
+            }
+            // x86 can't trap on reads alone, the closest we get is any access.
+            BreakpointKind::Watchpoint(Access::Write, len) => (0b01, len),
+            BreakpointKind::Watchpoint(_, len) => (0b11, len),
+        };
+        let len = match len {
+            2 => 0b01,
+            8 => 0b10,
+            4 => 0b11,
+            _ => 0b00,
+        };
+        (len << 18) | (rw << 16)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            BreakpointKind::Software => "breakpoint",
+            BreakpointKind::Hardware => "hw breakpoint",
+            BreakpointKind::Watchpoint(Access::Write, _) => "hw watchpoint",
+            BreakpointKind::Watchpoint(Access::Read, _) => "read watchpoint",
+            BreakpointKind::Watchpoint(Access::ReadWrite, _) => "acc watchpoint",
+            BreakpointKind::Syscall => "catchpoint",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Breakpoint {
+    pub id: usize,
+    pub kind: BreakpointKind,
+    /// What the user asked for, which `addr` was resolved from.
+    pub spec: Location,
+    pub addr: usize,
+    pub symbol: Option<String>,
+    pub line: Option<LineInfo>,
+    /// Not in the program that's currently loaded, e.g. after an exec.
+    pub pending: bool,
+    pub enabled: bool,
+    pub hit_count: usize,
+    /// Only stop when this evaluates to something non-zero.
+    pub condition: Option<Expr>,
+    /// How many more hits to let through before stopping.
+    pub ignore_count: usize,
+    /// Deleted the first time it stops us, e.g. from `tbreak`.
+    pub temporary: bool,
+    /// The last value seen at a watchpoint's address.
+    pub value: Option<u64>,
+    /// The syscall numbers a catchpoint stops at, all of them if empty.
+    pub syscalls: Vec<u64>,
+}
+
+impl Breakpoint {
+    /// A human readable description of where this breakpoint is, e.g.
+    /// `main+4 at hello.c:5`.
+    pub fn location(&self) -> String {
+        if self.kind == BreakpointKind::Syscall {
+            if self.syscalls.is_empty() {
+                return "syscall \"<any syscall>\"".to_owned();
+            }
+            let names: Vec<String> = self
+                .syscalls
+                .iter()
+                .map(|n| syscall::name(*n).map_or_else(|| n.to_string(), str::to_owned))
+                .collect();
+            return format!("syscall \"{}\"", names.join(" "));
+        }
+
+        let mut location = match self.symbol {
+            Some(ref symbol) => symbol.clone(),
+            None => format!("0x{:x}", self.addr),
+        };
+
+        if let Some(ref line) = self.line {
+            location.push_str(&format!(" at {}:{}", line.path.display(), line.line));
+        }
+
+        location
+    }
+}
+
+/// What a watchpoint saw when it stopped us.
+#[derive(Debug, Clone)]
+pub struct WatchpointHit {
+    pub id: usize,
+    pub old: u64,
+    pub new: u64,
+    /// Address of the instruction that did the access, if we could work it
+    /// out. The trap only fires once it has executed.
+    pub instruction: Option<usize>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn watchpoint_access() {
+        assert!(Access::Write.triggered_by(1, 2));
+        assert!(!Access::Write.triggered_by(1, 1));
+        assert!(Access::Read.triggered_by(1, 1));
+        assert!(!Access::Read.triggered_by(1, 2));
+        assert!(Access::ReadWrite.triggered_by(1, 1));
+        assert!(Access::ReadWrite.triggered_by(1, 2));
+    }
+}
@@ -9,6 +9,22 @@ impl Disassembler {
         Self {}
     }
 
+    /// Decodes as many whole instructions as fit in `bytes`.
+    pub fn decode(&self, rip: u64, bytes: &[u8]) -> Vec<Instruction> {
+        let mut decoder = Decoder::new(64, bytes, DecoderOptions::NONE);
+        decoder.set_ip(rip);
+
+        let mut instructions = Vec::new();
+        while decoder.can_decode() {
+            let instruction = decoder.decode();
+            if decoder.invalid_no_more_bytes() {
+                break;
+            }
+            instructions.push(instruction);
+        }
+        instructions
+    }
+
     pub fn disassemble(&self, rip: u64, bytes: &[u8]) -> Result<String> {
         let mut decoder = Decoder::new(64, &bytes, DecoderOptions::NONE);
         decoder.set_ip(rip);
@@ -0,0 +1,236 @@
+use super::function::{Expression, VariableLocation};
+use crate::debugger::{Registers, Subordinate};
+use crate::result::Result;
+use gimli::{
+    EndianSlice, Evaluation, EvaluationResult, Location, Piece, RunTimeEndian, UnitOffset, Value,
+};
+
+type Slice<'a> = EndianSlice<'a, RunTimeEndian>;
+
+/// What DWARF expressions are evaluated against: a frame of the
+/// subordinate, and what's known about the function it's running.
+pub struct Context<'a> {
+    pub subordinate: &'a Subordinate,
+    pub registers: &'a Registers,
+    /// Whether `registers` are the innermost frame's, the only frame where
+    /// all of them can be relied on.
+    pub innermost: bool,
+    /// The canonical frame address, for DW_OP_call_frame_cfa.
+    pub cfa: Option<usize>,
+    /// The frame's pc as the debug info has it: less the load bias, and
+    /// above the innermost frame, inside the call rather than after it.
+    pub pc: usize,
+    /// How far the code was loaded from the addresses in its debug info.
+    pub bias: usize,
+    pub frame_base: Option<&'a VariableLocation>,
+    /// The size of the program's own thread-local storage, which sits just
+    /// below the thread pointer.
+    pub tls_size: Option<usize>,
+}
+
+impl<'a> Context<'a> {
+    /// Reads `size` bytes of the value at a location, which may be in
+    /// several pieces.
+    pub fn read(&self, location: &VariableLocation, size: usize) -> Result<Vec<u8>> {
+        let expression = match location {
+            VariableLocation::Expression(expression) => expression,
+            VariableLocation::List(list) => {
+                match list.iter().find(|(range, _)| range.contains(&self.pc)) {
+                    Some((_, expression)) => expression,
+                    None => return Err("optimized out".into()),
+                }
+            }
+            VariableLocation::Constant(bytes) => {
+                let mut bytes = bytes.clone();
+                bytes.resize(size, 0);
+                return Ok(bytes);
+            }
+            VariableLocation::None => return Err("optimized out".into()),
+        };
+
+        let pieces = self.evaluate(expression, None)?;
+        if let [Piece {
+            size_in_bits: None,
+            location,
+            ..
+        }] = &pieces[..]
+        {
+            return self.read_piece(location, size);
+        }
+        let mut bytes = Vec::new();
+        for piece in &pieces {
+            let bits = match piece.size_in_bits {
+                Some(bits) => bits,
+                None => 8 * size.saturating_sub(bytes.len()) as u64,
+            };
+            if bits % 8 != 0 || piece.bit_offset.unwrap_or(0) != 0 {
+                return Err("can't read pieces that aren't whole bytes".into());
+            }
+            bytes.extend(self.read_piece(&piece.location, bits as usize / 8)?);
+        }
+        bytes.resize(size, 0);
+        Ok(bytes)
+    }
+
+    /// Evaluates an expression that gives an address, or a value used as
+    /// one, as for a frame base or in call frame information. `initial` is
+    /// pushed on the stack first.
+    pub fn address(&self, expression: &Expression, initial: Option<u64>) -> Result<u64> {
+        match &self.evaluate(expression, initial)?[..] {
+            [Piece { location, .. }] => match location {
+                Location::Address { address } => Ok(*address),
+                Location::Register { register } => self.register(register.0),
+                Location::Value { value } => Ok(value.to_u64(!0)?),
+                Location::Empty => Err("optimized out".into()),
+                _ => Err("DWARF expression doesn't give an address".into()),
+            },
+            _ => Err("DWARF expression doesn't give an address".into()),
+        }
+    }
+
+    fn evaluate<'e>(
+        &self,
+        expression: &'e Expression,
+        initial: Option<u64>,
+    ) -> Result<Vec<Piece<Slice<'e>>>> {
+        let bytecode = EndianSlice::new(&expression.bytes, expression.endian);
+        let mut evaluation = Evaluation::new(bytecode, expression.encoding);
+        if let Some(value) = initial {
+            evaluation.set_initial_value(value);
+        }
+
+        let mut result = evaluation.evaluate()?;
+        loop {
+            result = match result {
+                EvaluationResult::Complete => return Ok(evaluation.result()),
+                EvaluationResult::RequiresMemory {
+                    address,
+                    size,
+                    base_type,
+                    ..
+                } => {
+                    let bytes = self
+                        .subordinate
+                        .read_bytes(address as usize, size as usize)?;
+                    evaluation.resume_with_memory(typed(expression, base_type, &bytes)?)?
+                }
+                EvaluationResult::RequiresRegister {
+                    register,
+                    base_type,
+                } => {
+                    let bytes = self.register_bytes(register.0)?;
+                    evaluation.resume_with_register(typed(expression, base_type, &bytes)?)?
+                }
+                EvaluationResult::RequiresBaseType(offset) => {
+                    evaluation.resume_with_base_type(expression.base_type(offset)?)?
+                }
+                EvaluationResult::RequiresFrameBase => {
+                    evaluation.resume_with_frame_base(self.frame_base()?)?
+                }
+                EvaluationResult::RequiresTls(offset) => {
+                    evaluation.resume_with_tls(self.tls_address(offset)?)?
+                }
+                EvaluationResult::RequiresCallFrameCfa => match self.cfa {
+                    Some(cfa) => evaluation.resume_with_call_frame_cfa(cfa as u64)?,
+                    None => return Err("no canonical frame address".into()),
+                },
+                EvaluationResult::RequiresRelocatedAddress(address) => {
+                    evaluation.resume_with_relocated_address(address + self.bias as u64)?
+                }
+                // What a register held when the function was called. Only
+                // the caller could tell us, and it didn't keep it.
+                EvaluationResult::RequiresEntryValue(_) => return Err("optimized out".into()),
+                needs => return Err(format!("can't evaluate DWARF expression: {:?}", needs).into()),
+            };
+        }
+    }
+
+    fn read_piece(&self, location: &Location<Slice>, size: usize) -> Result<Vec<u8>> {
+        let mut bytes = match location {
+            Location::Empty => return Err("optimized out".into()),
+            Location::Address { address } => {
+                return self.subordinate.read_bytes(*address as usize, size)
+            }
+            Location::Register { register } => self.register_bytes(register.0)?,
+            Location::Value { value } => match value {
+                Value::F32(value) => value.to_le_bytes().to_vec(),
+                Value::F64(value) => value.to_le_bytes().to_vec(),
+                value => value.to_u64(!0)?.to_le_bytes().to_vec(),
+            },
+            Location::Bytes { value } => value.slice().to_vec(),
+            Location::ImplicitPointer { .. } => return Err("synthetic pointer".into()),
+        };
+        bytes.resize(size, 0);
+        Ok(bytes)
+    }
+
+    /// The value of DW_AT_frame_base, which DW_OP_fbreg is relative to.
+    fn frame_base(&self) -> Result<u64> {
+        let expression = match self.frame_base {
+            Some(VariableLocation::Expression(expression)) => expression,
+            Some(VariableLocation::List(list)) => {
+                match list.iter().find(|(range, _)| range.contains(&self.pc)) {
+                    Some((_, expression)) => expression,
+                    None => return Err("no frame base here".into()),
+                }
+            }
+            _ => return Err("function has no frame base".into()),
+        };
+        self.address(expression, None)
+    }
+
+    /// Where a thread-local variable of the program is, given its offset
+    /// in the program's TLS block. On x86-64 the block for the executable
+    /// ends where fs points.
+    fn tls_address(&self, offset: u64) -> Result<u64> {
+        let tls_size = match self.tls_size {
+            Some(tls_size) => tls_size as u64,
+            None => return Err("the program has no thread-local storage".into()),
+        };
+        Ok(self.registers.fs_base - tls_size + offset)
+    }
+
+    fn register(&self, register: u16) -> Result<u64> {
+        // Above the innermost frame, nothing puts back the registers a
+        // function is free to clobber.
+        let callee_saved = Registers::dwarf_name(register).is_some_and(Registers::callee_saved);
+        if !self.innermost && !callee_saved {
+            return Err("not saved".into());
+        }
+        match self.registers.get_dwarf(register) {
+            Some(value) => Ok(value),
+            None => Err(format!("can't read DWARF register {}", register).into()),
+        }
+    }
+
+    fn register_bytes(&self, register: u16) -> Result<Vec<u8>> {
+        // xmm0 to xmm15, where floating point values live.
+        if (17..=32).contains(&register) {
+            if !self.innermost {
+                return Err("not saved".into());
+            }
+            let xmm = self.subordinate.xmm(register as usize - 17)?;
+            return Ok(xmm.to_le_bytes().to_vec());
+        }
+        Ok(self.register(register)?.to_le_bytes().to_vec())
+    }
+}
+
+/// Makes a value of one of the expression's base types out of its bytes.
+fn typed(expression: &Expression, base_type: UnitOffset, bytes: &[u8]) -> Result<Value> {
+    if base_type.0 == 0 {
+        return Ok(Value::Generic(le_u64(bytes)));
+    }
+    let value_type = expression.base_type(base_type)?;
+    Ok(Value::parse(
+        value_type,
+        EndianSlice::new(bytes, expression.endian),
+    )?)
+}
+
+fn le_u64(bytes: &[u8]) -> u64 {
+    let mut value = [0; 8];
+    let n = bytes.len().min(8);
+    value[..n].copy_from_slice(&bytes[..n]);
+    u64::from_le_bytes(value)
+}
@@ -0,0 +1,691 @@
+use crate::result::Result;
+use gimli::{
+    AttributeValue, EntriesTreeNode, Operation, Reader, RunTimeEndian, UnitOffset, ValueType,
+};
+use std::collections::HashMap;
+use std::ops::Range;
+
+type Slice<'a> = gimli::EndianSlice<'a, RunTimeEndian>;
+
+/// How far the parser follows types that are made from other types, in case
+/// the debug info goes round in circles.
+const MAX_TYPE_DEPTH: usize = 32;
+
+/// A function that was compiled to code of its own, as opposed to only ever
+/// being inlined.
+#[derive(Debug, Clone)]
+pub struct Function {
+    /// What DW_OP_fbreg is relative to.
+    pub frame_base: Option<VariableLocation>,
+    pub parameters: Vec<Variable>,
+    /// The function's body, whose ranges are the function's code.
+    pub scope: Scope,
+}
+
+impl Function {
+    pub fn contains(&self, pc: usize) -> bool {
+        self.scope.contains(pc)
+    }
+
+    /// The local variables in scope at `pc`, innermost block first.
+    pub fn locals(&self, pc: usize) -> Vec<&Variable> {
+        let mut locals = Vec::new();
+        self.scope.collect(pc, &mut locals);
+        locals
+    }
+}
+
+/// The function body or a block within it.
+#[derive(Debug, Clone)]
+pub struct Scope {
+    pub ranges: Vec<Range<usize>>,
+    pub variables: Vec<Variable>,
+    pub scopes: Vec<Scope>,
+}
+
+impl Scope {
+    fn contains(&self, pc: usize) -> bool {
+        self.ranges.iter().any(|range| range.contains(&pc))
+    }
+
+    fn collect<'a>(&'a self, pc: usize, variables: &mut Vec<&'a Variable>) {
+        for scope in &self.scopes {
+            if scope.contains(pc) {
+                scope.collect(pc, variables);
+            }
+        }
+        variables.extend(&self.variables);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Variable {
+    pub name: String,
+    pub ty: Option<Type>,
+    pub location: VariableLocation,
+}
+
+/// Where to find a variable, or a function's frame base.
+#[derive(Debug, Clone)]
+pub enum VariableLocation {
+    Expression(Expression),
+    /// Different expressions for different ranges of pc.
+    List(Vec<(Range<usize>, Expression)>),
+    /// The value itself, from DW_AT_const_value.
+    Constant(Vec<u8>),
+    /// Optimized out everywhere.
+    None,
+}
+
+/// A DWARF expression, copied out of the section so that it doesn't borrow
+/// from it.
+#[derive(Debug, Clone)]
+pub struct Expression {
+    pub bytes: Vec<u8>,
+    pub encoding: gimli::Encoding,
+    pub endian: RunTimeEndian,
+    /// The base types that typed operations refer to, which can't be
+    /// looked up later without the unit.
+    pub base_types: Vec<(UnitOffset, ValueType)>,
+}
+
+impl Expression {
+    pub fn new(expression: gimli::Expression<Slice>, encoding: gimli::Encoding) -> Self {
+        Expression {
+            bytes: expression.0.slice().to_vec(),
+            encoding,
+            endian: expression.0.endian(),
+            base_types: Vec::new(),
+        }
+    }
+
+    pub fn base_type(&self, offset: UnitOffset) -> Result<ValueType> {
+        match self.base_types.iter().find(|(o, _)| *o == offset) {
+            Some((_, value_type)) => Ok(*value_type),
+            None => Err(format!("no base type at {:?}", offset).into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Type {
+    /// As it would be written in C, e.g. `const char *`.
+    pub name: String,
+    pub size: usize,
+    pub kind: TypeKind,
+}
+
+#[derive(Debug, Clone)]
+pub enum TypeKind {
+    Signed,
+    Unsigned,
+    Float,
+    Bool,
+    Char,
+    Pointer,
+    Array(Box<Type>, usize),
+    Enum(Vec<(String, i64)>),
+    /// Members with their offsets, for structs, classes and unions alike.
+    Struct(Vec<(String, usize, Type)>),
+    /// Anything we don't know how to show other than as bytes.
+    Other,
+}
+
+impl Type {
+    /// Shows a value of this type, given its bytes in memory.
+    pub fn format(&self, bytes: &[u8]) -> String {
+        if bytes.len() < self.size {
+            return format!("<{} of {} bytes>", bytes.len(), self.size);
+        }
+        let bytes = &bytes[..self.size];
+        match &self.kind {
+            TypeKind::Signed if self.size <= 8 => signed(bytes).to_string(),
+            TypeKind::Unsigned if self.size <= 8 => unsigned(bytes).to_string(),
+            TypeKind::Bool => (unsigned(bytes) != 0).to_string(),
+            TypeKind::Char if self.size == 1 => {
+                let c = bytes[0] as char;
+                format!("{} '{}'", bytes[0] as i8, c.escape_default())
+            }
+            TypeKind::Float if self.size == 4 => f32::from_bits(unsigned(bytes) as u32).to_string(),
+            TypeKind::Float if self.size == 8 => f64::from_bits(unsigned(bytes)).to_string(),
+            TypeKind::Pointer => format!("({}) 0x{:x}", self.name, unsigned(bytes)),
+            TypeKind::Array(element, count) if element.size > 0 => {
+                let elements: Vec<String> = bytes
+                    .chunks(element.size)
+                    .take(*count)
+                    .map(|bytes| element.format(bytes))
+                    .collect();
+                format!("{{{}}}", elements.join(", "))
+            }
+            TypeKind::Enum(values) if self.size <= 8 => {
+                let value = signed(bytes);
+                match values.iter().find(|(_, v)| *v == value) {
+                    Some((name, _)) => name.clone(),
+                    None => value.to_string(),
+                }
+            }
+            TypeKind::Struct(members) => {
+                let members: Vec<String> = members
+                    .iter()
+                    .map(|(name, offset, ty)| {
+                        let value = match bytes.get(*offset..) {
+                            Some(bytes) => ty.format(bytes),
+                            None => "?".to_owned(),
+                        };
+                        format!("{} = {}", name, value)
+                    })
+                    .collect();
+                format!("{{{}}}", members.join(", "))
+            }
+            _ => {
+                let bytes: Vec<String> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+                format!("<{}>", bytes.join(" "))
+            }
+        }
+    }
+}
+
+fn unsigned(bytes: &[u8]) -> u64 {
+    let mut value = [0; 8];
+    value[..bytes.len()].copy_from_slice(bytes);
+    u64::from_le_bytes(value)
+}
+
+fn signed(bytes: &[u8]) -> i64 {
+    let shift = 64 - 8 * bytes.len() as u32;
+    if shift >= 64 {
+        return 0;
+    }
+    ((unsigned(bytes) << shift) as i64) >> shift
+}
+
+/// Finds the functions in a compilation unit, with their parameters and
+/// variables.
+pub fn parse_functions(
+    dwarf: &gimli::Dwarf<Slice>,
+    unit: &gimli::Unit<Slice>,
+    functions: &mut Vec<Function>,
+) -> Result<()> {
+    let mut parser = Parser {
+        dwarf,
+        unit,
+        types: HashMap::new(),
+    };
+    let mut tree = unit.entries_tree(None)?;
+    parser.functions(tree.root()?, functions)
+}
+
+struct Parser<'a> {
+    dwarf: &'a gimli::Dwarf<Slice<'a>>,
+    unit: &'a gimli::Unit<Slice<'a>>,
+    // Types already parsed, by where they are in the unit.
+    types: HashMap<UnitOffset, Type>,
+}
+
+impl<'a> Parser<'a> {
+    fn functions(
+        &mut self,
+        node: EntriesTreeNode<Slice<'a>>,
+        functions: &mut Vec<Function>,
+    ) -> Result<()> {
+        let mut children = node.children();
+        while let Some(child) = children.next()? {
+            match child.entry().tag() {
+                gimli::DW_TAG_subprogram => {
+                    if let Some(function) = self.function(child)? {
+                        functions.push(function);
+                    }
+                }
+                gimli::DW_TAG_namespace
+                | gimli::DW_TAG_structure_type
+                | gimli::DW_TAG_class_type => self.functions(child, functions)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn function(&mut self, node: EntriesTreeNode<Slice<'a>>) -> Result<Option<Function>> {
+        let entry = node.entry();
+        let ranges = self.ranges(entry)?;
+        // Declarations, and functions that were only ever inlined.
+        if ranges.is_empty() {
+            return Ok(None);
+        }
+        let frame_base = match entry.attr_value(gimli::DW_AT_frame_base)? {
+            Some(value) => Some(self.location(value)?),
+            None => None,
+        };
+
+        let mut function = Function {
+            frame_base,
+            parameters: Vec::new(),
+            scope: Scope {
+                ranges,
+                variables: Vec::new(),
+                scopes: Vec::new(),
+            },
+        };
+        self.scope(node, &mut function.scope, Some(&mut function.parameters))?;
+        Ok(Some(function))
+    }
+
+    fn scope(
+        &mut self,
+        node: EntriesTreeNode<Slice<'a>>,
+        scope: &mut Scope,
+        mut parameters: Option<&mut Vec<Variable>>,
+    ) -> Result<()> {
+        let mut children = node.children();
+        while let Some(child) = children.next()? {
+            let entry = child.entry();
+            match entry.tag() {
+                gimli::DW_TAG_formal_parameter => {
+                    if let (Some(parameters), Some(variable)) =
+                        (parameters.as_mut(), self.variable(entry)?)
+                    {
+                        parameters.push(variable);
+                    }
+                }
+                gimli::DW_TAG_variable => {
+                    if let Some(variable) = self.variable(entry)? {
+                        scope.variables.push(variable);
+                    }
+                }
+                gimli::DW_TAG_lexical_block => {
+                    let mut block = Scope {
+                        ranges: self.ranges(entry)?,
+                        variables: Vec::new(),
+                        scopes: Vec::new(),
+                    };
+                    self.scope(child, &mut block, None)?;
+                    scope.scopes.push(block);
+                }
+                // Inlined calls have variables of their own, which aren't
+                // the caller's.
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn variable(
+        &mut self,
+        entry: &gimli::DebuggingInformationEntry<Slice<'a>>,
+    ) -> Result<Option<Variable>> {
+        let name = match self.name(entry)? {
+            Some(name) => name,
+            None => return Ok(None),
+        };
+        let ty = match self.attr(entry, gimli::DW_AT_type)? {
+            Some(AttributeValue::UnitRef(offset)) => Some(self.ty(offset)?),
+            _ => None,
+        };
+        let location = match entry.attr_value(gimli::DW_AT_location)? {
+            Some(value) => self.location(value)?,
+            None => match self.attr(entry, gimli::DW_AT_const_value)? {
+                Some(value) => VariableLocation::Constant(constant(value)),
+                None => VariableLocation::None,
+            },
+        };
+        Ok(Some(Variable { name, ty, location }))
+    }
+
+    fn location(&self, value: AttributeValue<Slice<'a>>) -> Result<VariableLocation> {
+        if let AttributeValue::Exprloc(expression) = value {
+            return Ok(VariableLocation::Expression(self.expression(expression)?));
+        }
+        let mut locations = match self.dwarf.attr_locations(self.unit, value)? {
+            Some(locations) => locations,
+            None => return Ok(VariableLocation::None),
+        };
+        let mut list = Vec::new();
+        while let Some(location) = locations.next()? {
+            let range = location.range.begin as usize..location.range.end as usize;
+            list.push((range, self.expression(location.data)?));
+        }
+        Ok(VariableLocation::List(list))
+    }
+
+    fn expression(&self, expression: gimli::Expression<Slice<'a>>) -> Result<Expression> {
+        let encoding = self.unit.encoding();
+        let mut copy = Expression::new(expression, encoding);
+        let mut bytes = expression.0;
+        while !bytes.is_empty() {
+            // Anything gimli can't parse, it can't evaluate either, which
+            // is for later to say.
+            let operation = match Operation::parse(&mut bytes, &expression.0, encoding) {
+                Ok(operation) => operation,
+                Err(_) => break,
+            };
+            let base_type = match operation {
+                Operation::Deref { base_type, .. }
+                | Operation::RegisterOffset { base_type, .. }
+                | Operation::TypedLiteral { base_type, .. }
+                | Operation::Convert { base_type }
+                | Operation::Reinterpret { base_type } => base_type,
+                _ => continue,
+            };
+            // 0 is the generic type, which needs no looking up.
+            if base_type.0 == 0 || copy.base_type(base_type).is_ok() {
+                continue;
+            }
+            let mut entries = self.unit.entries_at_offset(base_type)?;
+            entries.next_entry()?;
+            if let Some(entry) = entries.current() {
+                if let Some(value_type) = ValueType::from_entry(entry)? {
+                    copy.base_types.push((base_type, value_type));
+                }
+            }
+        }
+        Ok(copy)
+    }
+
+    fn ranges(
+        &self,
+        entry: &gimli::DebuggingInformationEntry<Slice<'a>>,
+    ) -> Result<Vec<Range<usize>>> {
+        let mut ranges = Vec::new();
+        let mut iter = self.dwarf.die_ranges(self.unit, entry)?;
+        while let Some(range) = iter.next()? {
+            if range.begin < range.end {
+                ranges.push(range.begin as usize..range.end as usize);
+            }
+        }
+        Ok(ranges)
+    }
+
+    fn name(&self, entry: &gimli::DebuggingInformationEntry<Slice<'a>>) -> Result<Option<String>> {
+        match self.attr(entry, gimli::DW_AT_name)? {
+            Some(value) => {
+                let name = self.dwarf.attr_string(self.unit, value)?;
+                Ok(Some(name.to_string_lossy().into_owned()))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Looks up an attribute, going to the abstract instance or declaration
+    /// for it if this entry doesn't have it itself.
+    fn attr(
+        &self,
+        entry: &gimli::DebuggingInformationEntry<Slice<'a>>,
+        name: gimli::DwAt,
+    ) -> Result<Option<AttributeValue<Slice<'a>>>> {
+        if let Some(value) = entry.attr_value(name)? {
+            return Ok(Some(value));
+        }
+        for link in &[gimli::DW_AT_abstract_origin, gimli::DW_AT_specification] {
+            if let Some(AttributeValue::UnitRef(offset)) = entry.attr_value(*link)? {
+                let mut entries = self.unit.entries_at_offset(offset)?;
+                entries.next_entry()?;
+                if let Some(origin) = entries.current() {
+                    return self.attr(origin, name);
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    fn ty(&mut self, offset: UnitOffset) -> Result<Type> {
+        if let Some(ty) = self.types.get(&offset) {
+            return Ok(ty.clone());
+        }
+        let ty = self.parse_type(Some(offset), 0)?;
+        self.types.insert(offset, ty.clone());
+        Ok(ty)
+    }
+
+    fn parse_type(&self, offset: Option<UnitOffset>, depth: usize) -> Result<Type> {
+        let name = self.type_name(offset, depth)?;
+        let offset = match offset {
+            Some(offset) if depth < MAX_TYPE_DEPTH => offset,
+            _ => {
+                return Ok(Type {
+                    name,
+                    size: 0,
+                    kind: TypeKind::Other,
+                })
+            }
+        };
+        let mut tree = self.unit.entries_tree(Some(offset))?;
+        let node = tree.root()?;
+        let entry = node.entry();
+        let size = entry
+            .attr_value(gimli::DW_AT_byte_size)?
+            .and_then(|value| value.udata_value())
+            .unwrap_or(0) as usize;
+        let inner = match entry.attr_value(gimli::DW_AT_type)? {
+            Some(AttributeValue::UnitRef(offset)) => Some(offset),
+            _ => None,
+        };
+
+        let kind = match entry.tag() {
+            gimli::DW_TAG_base_type => match entry.attr_value(gimli::DW_AT_encoding)? {
+                Some(AttributeValue::Encoding(gimli::DW_ATE_signed)) => TypeKind::Signed,
+                Some(AttributeValue::Encoding(gimli::DW_ATE_unsigned)) => TypeKind::Unsigned,
+                Some(AttributeValue::Encoding(gimli::DW_ATE_float)) => TypeKind::Float,
+                Some(AttributeValue::Encoding(gimli::DW_ATE_boolean)) => TypeKind::Bool,
+                Some(AttributeValue::Encoding(gimli::DW_ATE_signed_char))
+                | Some(AttributeValue::Encoding(gimli::DW_ATE_unsigned_char)) => TypeKind::Char,
+                _ => TypeKind::Other,
+            },
+            gimli::DW_TAG_typedef
+            | gimli::DW_TAG_const_type
+            | gimli::DW_TAG_volatile_type
+            | gimli::DW_TAG_restrict_type
+            | gimli::DW_TAG_atomic_type => {
+                let ty = self.parse_type(inner, depth + 1)?;
+                return Ok(Type { name, ..ty });
+            }
+            gimli::DW_TAG_pointer_type
+            | gimli::DW_TAG_reference_type
+            | gimli::DW_TAG_rvalue_reference_type => {
+                return Ok(Type {
+                    name,
+                    size: if size == 0 { 8 } else { size },
+                    kind: TypeKind::Pointer,
+                });
+            }
+            gimli::DW_TAG_array_type => {
+                let mut element = self.parse_type(inner, depth + 1)?;
+                let mut counts = Vec::new();
+                let mut children = node.children();
+                while let Some(child) = children.next()? {
+                    let entry = child.entry();
+                    if entry.tag() != gimli::DW_TAG_subrange_type {
+                        continue;
+                    }
+                    let count = match entry.attr_value(gimli::DW_AT_count)? {
+                        Some(count) => count.udata_value(),
+                        None => entry
+                            .attr_value(gimli::DW_AT_upper_bound)?
+                            .and_then(|bound| bound.udata_value())
+                            .map(|bound| bound + 1),
+                    };
+                    counts.push(count.unwrap_or(0) as usize);
+                }
+                // int a[2][3] is an array of 2 arrays of 3 ints.
+                let base = self.type_name(inner, depth + 1)?;
+                for (i, count) in counts.iter().enumerate().rev() {
+                    let dimensions: String =
+                        counts[i..].iter().map(|n| format!("[{}]", n)).collect();
+                    element = Type {
+                        name: format!("{} {}", base, dimensions),
+                        size: element.size * count,
+                        kind: TypeKind::Array(Box::new(element), *count),
+                    };
+                }
+                return Ok(element);
+            }
+            gimli::DW_TAG_enumeration_type => {
+                let mut values = Vec::new();
+                let mut children = node.children();
+                while let Some(child) = children.next()? {
+                    let entry = child.entry();
+                    let value = match entry.attr_value(gimli::DW_AT_const_value)? {
+                        Some(AttributeValue::Sdata(value)) => value,
+                        Some(value) => value.udata_value().unwrap_or(0) as i64,
+                        None => continue,
+                    };
+                    if let Some(name) = self.name(entry)? {
+                        values.push((name, value));
+                    }
+                }
+                TypeKind::Enum(values)
+            }
+            gimli::DW_TAG_structure_type | gimli::DW_TAG_class_type | gimli::DW_TAG_union_type => {
+                let mut members = Vec::new();
+                let mut children = node.children();
+                while let Some(child) = children.next()? {
+                    let entry = child.entry();
+                    if entry.tag() != gimli::DW_TAG_member {
+                        continue;
+                    }
+                    let offset = entry
+                        .attr_value(gimli::DW_AT_data_member_location)?
+                        .and_then(|offset| offset.udata_value())
+                        .unwrap_or(0) as usize;
+                    let ty = match entry.attr_value(gimli::DW_AT_type)? {
+                        Some(AttributeValue::UnitRef(ty)) => Some(ty),
+                        _ => None,
+                    };
+                    let name = self.name(entry)?.unwrap_or_default();
+                    members.push((name, offset, self.parse_type(ty, depth + 1)?));
+                }
+                TypeKind::Struct(members)
+            }
+            _ => TypeKind::Other,
+        };
+        Ok(Type { name, size, kind })
+    }
+
+    /// The name of a type as it would be written in C.
+    fn type_name(&self, offset: Option<UnitOffset>, depth: usize) -> Result<String> {
+        let offset = match offset {
+            Some(offset) if depth < MAX_TYPE_DEPTH => offset,
+            Some(_) => return Ok("?".to_owned()),
+            None => return Ok("void".to_owned()),
+        };
+        let mut entries = self.unit.entries_at_offset(offset)?;
+        entries.next_entry()?;
+        let entry = match entries.current() {
+            Some(entry) => entry,
+            None => return Ok("?".to_owned()),
+        };
+        let name = self.name(entry)?;
+        let inner = match entry.attr_value(gimli::DW_AT_type)? {
+            Some(AttributeValue::UnitRef(offset)) => Some(offset),
+            _ => None,
+        };
+        let tagged = |tag: &str| match &name {
+            Some(name) => format!("{} {}", tag, name),
+            None => format!("{} {{...}}", tag),
+        };
+
+        Ok(match entry.tag() {
+            gimli::DW_TAG_structure_type => tagged("struct"),
+            gimli::DW_TAG_class_type => tagged("class"),
+            gimli::DW_TAG_union_type => tagged("union"),
+            gimli::DW_TAG_enumeration_type => tagged("enum"),
+            gimli::DW_TAG_pointer_type => format!("{} *", self.type_name(inner, depth + 1)?),
+            gimli::DW_TAG_reference_type => format!("{} &", self.type_name(inner, depth + 1)?),
+            gimli::DW_TAG_rvalue_reference_type => {
+                format!("{} &&", self.type_name(inner, depth + 1)?)
+            }
+            gimli::DW_TAG_const_type => format!("const {}", self.type_name(inner, depth + 1)?),
+            gimli::DW_TAG_volatile_type => {
+                format!("volatile {}", self.type_name(inner, depth + 1)?)
+            }
+            gimli::DW_TAG_restrict_type | gimli::DW_TAG_atomic_type => {
+                self.type_name(inner, depth + 1)?
+            }
+            gimli::DW_TAG_array_type => format!("{} []", self.type_name(inner, depth + 1)?),
+            gimli::DW_TAG_subroutine_type => {
+                format!("{} ()", self.type_name(inner, depth + 1)?)
+            }
+            _ => name.unwrap_or_else(|| "?".to_owned()),
+        })
+    }
+}
+
+/// The bytes of a DW_AT_const_value, little end first.
+fn constant(value: AttributeValue<Slice>) -> Vec<u8> {
+    match value {
+        AttributeValue::Block(block) => block.slice().to_vec(),
+        AttributeValue::Sdata(value) => value.to_le_bytes().to_vec(),
+        value => value.udata_value().unwrap_or(0).to_le_bytes().to_vec(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ty(name: &str, size: usize, kind: TypeKind) -> Type {
+        Type {
+            name: name.to_owned(),
+            size,
+            kind,
+        }
+    }
+
+    #[test]
+    fn base_types() {
+        let int = ty("int", 4, TypeKind::Signed);
+        assert_eq!(int.format(&(-5i32).to_le_bytes()), "-5");
+        let short = ty("unsigned short", 2, TypeKind::Unsigned);
+        assert_eq!(short.format(&[0xff, 0xff, 0x12]), "65535");
+        let long = ty("long", 8, TypeKind::Signed);
+        assert_eq!(long.format(&i64::MIN.to_le_bytes()), i64::MIN.to_string());
+        let b = ty("_Bool", 1, TypeKind::Bool);
+        assert_eq!(b.format(&[2]), "true");
+        let c = ty("char", 1, TypeKind::Char);
+        assert_eq!(c.format(b"z"), "122 'z'");
+        assert_eq!(c.format(b"\n"), "10 '\\n'");
+        assert_eq!(c.format(&[0xe9]), "-23 '\\u{e9}'");
+        let float = ty("float", 4, TypeKind::Float);
+        assert_eq!(float.format(&2.5f32.to_le_bytes()), "2.5");
+        let double = ty("double", 8, TypeKind::Float);
+        assert_eq!(double.format(&(-0.125f64).to_le_bytes()), "-0.125");
+        let ptr = ty("char *", 8, TypeKind::Pointer);
+        assert_eq!(ptr.format(&0x4010u64.to_le_bytes()), "(char *) 0x4010");
+    }
+
+    #[test]
+    fn short_or_odd_values() {
+        let int = ty("int", 4, TypeKind::Signed);
+        assert_eq!(int.format(&[1, 2]), "<2 of 4 bytes>");
+        let wide = ty("__int128", 16, TypeKind::Signed);
+        assert_eq!(
+            wide.format(&[0xab; 16]),
+            format!("<{}>", vec!["ab"; 16].join(" "))
+        );
+        let empty = ty("struct empty", 0, TypeKind::Struct(Vec::new()));
+        assert_eq!(empty.format(&[]), "{}");
+    }
+
+    #[test]
+    fn aggregates() {
+        let int = ty("int", 4, TypeKind::Signed);
+        let array = ty("int [3]", 12, TypeKind::Array(Box::new(int.clone()), 3));
+        let bytes: Vec<u8> = [1i32, -2, 3].iter().flat_map(|i| i.to_le_bytes()).collect();
+        assert_eq!(array.format(&bytes), "{1, -2, 3}");
+
+        let color = TypeKind::Enum(vec![("RED".to_owned(), 0), ("GREEN".to_owned(), 1)]);
+        let color = ty("enum color", 4, color);
+        assert_eq!(color.format(&1u32.to_le_bytes()), "GREEN");
+        assert_eq!(color.format(&7u32.to_le_bytes()), "7");
+
+        let members = vec![
+            ("x".to_owned(), 0, int.clone()),
+            ("c".to_owned(), 4, ty("char", 1, TypeKind::Char)),
+            ("p".to_owned(), 8, array),
+        ];
+        let point = ty("struct point", 20, TypeKind::Struct(members));
+        let mut bytes = 3i32.to_le_bytes().to_vec();
+        bytes.extend(&[b'a', 0, 0, 0]);
+        bytes.extend([4i32, 5, 6].iter().flat_map(|i| i.to_le_bytes()));
+        assert_eq!(point.format(&bytes), "{x = 3, c = 97 'a', p = {4, 5, 6}}");
+    }
+}
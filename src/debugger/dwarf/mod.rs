@@ -1,25 +1,50 @@
+mod eval;
+mod function;
+
+pub use eval::Context;
+pub use function::{Expression, Function, Variable};
+
 use crate::result::Result;
 use object::{Object, ObjectSection};
-use std::collections::HashMap;
-use std::{borrow, fs::File, path::PathBuf};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+use std::{borrow, fs, fs::File, path::PathBuf};
 
 #[derive(Debug, Clone)]
 pub struct LineInfo {
     pub path: PathBuf,
     pub line: u64,
     pub column: u64,
+    /// Whether this is a recommended place to stop for the line, as opposed
+    /// to somewhere in the middle of it.
+    pub is_stmt: bool,
+}
+
+impl LineInfo {
+    pub fn same_line(&self, other: &LineInfo) -> bool {
+        self.line == other.line && self.path == other.path
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct DebugInfo {
-    pc_to_line: HashMap<usize, LineInfo>,
-    source_code: HashMap<PathBuf, Vec<String>>,
+    // `None` marks the end of a sequence, everything from there up to the
+    // next entry has no line information.
+    pc_to_line: BTreeMap<usize, Option<LineInfo>>,
+    // The addresses where execution enters each line, only counting rows
+    // flagged as good places to stop.
+    line_to_pcs: HashMap<PathBuf, BTreeMap<u64, Vec<usize>>>,
+    // Read the first time a line of the file is wanted. `None` for files we
+    // couldn't read, so we don't keep trying.
+    source_code: RefCell<HashMap<PathBuf, Option<Rc<Vec<String>>>>>,
+    functions: Vec<Function>,
 }
 
 impl DebugInfo {
     pub fn new(file: File) -> Result<Self> {
-        let mut pc_to_line: HashMap<usize, LineInfo> = HashMap::new();
-        let mut source_code: HashMap<PathBuf, Vec<String>> = HashMap::new();
+        let mut pc_to_line: BTreeMap<usize, Option<LineInfo>> = BTreeMap::new();
+        let mut functions = Vec::new();
 
         let mmap = unsafe { memmap::Mmap::map(&file).unwrap() };
         let object = object::File::parse(&*mmap).unwrap();
@@ -59,6 +84,7 @@ impl DebugInfo {
         let mut iter = dwarf.units();
         while let Some(header) = iter.next()? {
             let unit = dwarf.unit(header)?;
+            function::parse_functions(&dwarf, &unit, &mut functions)?;
 
             // Get the line program for the compilation unit.
             if let Some(program) = unit.line_program.clone() {
@@ -70,8 +96,11 @@ impl DebugInfo {
 
                 // Iterate over the line program rows.
                 let mut rows = program.rows();
+                let mut sequence = Vec::new();
                 while let Some((header, row)) = rows.next_row()? {
                     if row.end_sequence() {
+                        add_sequence(&mut pc_to_line, &sequence, row.address() as usize);
+                        sequence.clear();
                         continue;
                     }
 
@@ -90,18 +119,6 @@ impl DebugInfo {
                         );
                     }
 
-                    // if !path.exists() {
-                    //     continue;
-                    // }
-
-                    // if !source_code.contains_key(&path) {
-                    //     let lines: Vec<String> = Vec::new();
-                    //     for line in BufReader::new(File::open(&path)?).lines() {
-                    //         lines.push(line?);
-                    //     }
-                    //     source_code.insert(path.clone(), lines);
-                    // }
-
                     // Determine line/column. DWARF line/column is never 0, so we use that
                     // but other applications may want to display this differently.
                     let line = row.line().unwrap_or(0);
@@ -110,22 +127,203 @@ impl DebugInfo {
                         gimli::ColumnType::Column(x) => x,
                     };
 
-                    pc_to_line.insert(row.address() as usize, LineInfo { path, line, column });
+                    let is_stmt = row.is_stmt();
+                    sequence.push((
+                        row.address() as usize,
+                        LineInfo {
+                            path,
+                            line,
+                            column,
+                            is_stmt,
+                        },
+                    ));
                 }
             }
         }
 
+        let line_to_pcs = reverse_line_table(&pc_to_line);
+
         Ok(DebugInfo {
             pc_to_line,
-            source_code,
+            line_to_pcs,
+            source_code: RefCell::default(),
+            functions,
         })
     }
 
+    /// Line information for an address that starts a row in the line table.
     pub fn line_info(&self, rip: usize) -> Option<&LineInfo> {
-        self.pc_to_line.get(&rip)
+        self.pc_to_line.get(&rip).and_then(Option::as_ref)
+    }
+
+    /// Line information for any address, including ones in the middle of a
+    /// line.
+    pub fn line_for_pc(&self, rip: usize) -> Option<&LineInfo> {
+        self.pc_to_line
+            .range(..=rip)
+            .next_back()
+            .and_then(|(_, line)| line.as_ref())
+    }
+
+    /// Finds the addresses to stop at for a line in any file whose path ends
+    /// with `file`. If the line has no code, the next line that does is used.
+    pub fn addrs_for_line(&self, file: &str, line: u64) -> Vec<usize> {
+        let mut addrs = Vec::new();
+        for (path, lines) in &self.line_to_pcs {
+            if !path.ends_with(file) {
+                continue;
+            }
+            if let Some((_, pcs)) = lines.range(line..).next() {
+                addrs.extend(pcs);
+            }
+        }
+        addrs.sort();
+        addrs
+    }
+
+    /// The function whose code `pc` is in, if it has debug info.
+    pub fn function_for_pc(&self, pc: usize) -> Option<&Function> {
+        self.functions.iter().find(|function| function.contains(pc))
+    }
+
+    /// The lines of a source file, which needn't be valid UTF-8.
+    pub fn lines(&self, path: &PathBuf) -> Option<Rc<Vec<String>>> {
+        self.source_code
+            .borrow_mut()
+            .entry(path.clone())
+            .or_insert_with(|| {
+                let text = String::from_utf8_lossy(&fs::read(path).ok()?).into_owned();
+                Some(Rc::new(text.lines().map(String::from).collect()))
+            })
+            .clone()
+    }
+}
+
+/// Adds the rows of one sequence of a line program, which ends at `end`.
+fn add_sequence(
+    pc_to_line: &mut BTreeMap<usize, Option<LineInfo>>,
+    sequence: &[(usize, LineInfo)],
+    end: usize,
+) {
+    for (i, (addr, info)) in sequence.iter().enumerate() {
+        // A row that starts where the next one does covers no code.
+        let next = sequence.get(i + 1).map_or(end, |(next, _)| *next);
+        // Line 0 is code that isn't from any line, such as what the compiler
+        // adds. It's left to the row before, so that stepping goes through it.
+        if *addr != next && info.line != 0 {
+            pc_to_line.insert(*addr, Some(info.clone()));
+        }
     }
+    // Another sequence may start where this one ends.
+    pc_to_line.entry(end).or_insert(None);
+}
+
+fn reverse_line_table(
+    pc_to_line: &BTreeMap<usize, Option<LineInfo>>,
+) -> HashMap<PathBuf, BTreeMap<u64, Vec<usize>>> {
+    let mut line_to_pcs: HashMap<PathBuf, BTreeMap<u64, Vec<usize>>> = HashMap::new();
+    let mut prev: Option<&LineInfo> = None;
+
+    for (addr, info) in pc_to_line {
+        let info = match info {
+            Some(info) => info,
+            None => {
+                prev = None;
+                continue;
+            }
+        };
+
+        // A line is often split over several rows in a row, we only want
+        // to stop once when we get to it.
+        let continues_line = prev.is_some_and(|prev| prev.same_line(info));
+        if info.is_stmt && !continues_line {
+            line_to_pcs
+                .entry(info.path.clone())
+                .or_default()
+                .entry(info.line)
+                .or_default()
+                .push(*addr);
+        }
+        prev = Some(info);
+    }
+
+    line_to_pcs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(addr: usize, line: u64) -> (usize, LineInfo) {
+        let info = LineInfo {
+            path: PathBuf::from("/src/main.c"),
+            line,
+            column: 0,
+            is_stmt: true,
+        };
+        (addr, info)
+    }
+
+    fn lines(pc_to_line: &BTreeMap<usize, Option<LineInfo>>) -> Vec<(usize, Option<u64>)> {
+        pc_to_line
+            .iter()
+            .map(|(addr, info)| (*addr, info.as_ref().map(|info| info.line)))
+            .collect()
+    }
+
+    #[test]
+    fn adjacent_sequences() {
+        let first = [row(0x10, 1), row(0x20, 2)];
+        let second = [row(0x30, 10), row(0x38, 11)];
+        let expected = vec![
+            (0x10, Some(1)),
+            (0x20, Some(2)),
+            (0x30, Some(10)),
+            (0x38, Some(11)),
+            (0x40, None),
+        ];
+
+        let mut pc_to_line = BTreeMap::new();
+        add_sequence(&mut pc_to_line, &first, 0x30);
+        add_sequence(&mut pc_to_line, &second, 0x40);
+        assert_eq!(lines(&pc_to_line), expected);
+
+        let mut pc_to_line = BTreeMap::new();
+        add_sequence(&mut pc_to_line, &second, 0x40);
+        add_sequence(&mut pc_to_line, &first, 0x30);
+        assert_eq!(lines(&pc_to_line), expected);
+    }
+
+    #[test]
+    fn empty_rows_are_dropped() {
+        // Line 3 starts where the sequence ends, and line 5 is replaced by
+        // line 6 before any code.
+        let first = [row(0x10, 1), row(0x30, 3)];
+        let second = [row(0x30, 5), row(0x30, 6), row(0x38, 7)];
+
+        let mut pc_to_line = BTreeMap::new();
+        add_sequence(&mut pc_to_line, &second, 0x40);
+        add_sequence(&mut pc_to_line, &first, 0x30);
+        assert_eq!(
+            lines(&pc_to_line),
+            vec![
+                (0x10, Some(1)),
+                (0x30, Some(6)),
+                (0x38, Some(7)),
+                (0x40, None)
+            ]
+        );
+    }
+
+    #[test]
+    fn line_zero_rows_are_dropped() {
+        let sequence = [row(0x10, 1), row(0x18, 0), row(0x20, 2)];
 
-    pub fn lines(&self, path: &PathBuf) -> Option<&Vec<String>> {
-        self.source_code.get(path)
+        let mut pc_to_line = BTreeMap::new();
+        add_sequence(&mut pc_to_line, &sequence, 0x30);
+        assert_eq!(
+            lines(&pc_to_line),
+            vec![(0x10, Some(1)), (0x20, Some(2)), (0x30, None)]
+        );
     }
 }
@@ -0,0 +1,459 @@
+use crate::debugger::{Registers, Subordinate};
+use crate::result::Result;
+use std::fmt;
+
+/// A tiny expression language for poking at the state of the subordinate,
+/// e.g. `rdi == 0x10 && *(rsp + 8) > 3`. All values are 64 bit signed
+/// integers, and anything that isn't a literal or a register name is looked
+/// up in the symbol table.
+#[derive(Debug, Clone)]
+pub enum Expr {
+    Int(i64),
+    Register(String),
+    Symbol(String),
+    Deref(Box<Expr>),
+    Unary(UnOp, Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnOp {
+    Neg,
+    Not,
+    BitNot,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinOp {
+    Mul,
+    Div,
+    Rem,
+    Add,
+    Sub,
+    Shl,
+    Shr,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    BitAnd,
+    BitXor,
+    BitOr,
+    And,
+    Or,
+}
+
+impl BinOp {
+    fn precedence(self) -> u8 {
+        match self {
+            BinOp::Mul | BinOp::Div | BinOp::Rem => 10,
+            BinOp::Add | BinOp::Sub => 9,
+            BinOp::Shl | BinOp::Shr => 8,
+            BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge => 7,
+            BinOp::Eq | BinOp::Ne => 6,
+            BinOp::BitAnd => 5,
+            BinOp::BitXor => 4,
+            BinOp::BitOr => 3,
+            BinOp::And => 2,
+            BinOp::Or => 1,
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            BinOp::Mul => "*",
+            BinOp::Div => "/",
+            BinOp::Rem => "%",
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Shl => "<<",
+            BinOp::Shr => ">>",
+            BinOp::Lt => "<",
+            BinOp::Le => "<=",
+            BinOp::Gt => ">",
+            BinOp::Ge => ">=",
+            BinOp::Eq => "==",
+            BinOp::Ne => "!=",
+            BinOp::BitAnd => "&",
+            BinOp::BitXor => "^",
+            BinOp::BitOr => "|",
+            BinOp::And => "&&",
+            BinOp::Or => "||",
+        }
+    }
+}
+
+/// What evaluating an expression needs to look up.
+pub trait Target {
+    fn register(&self, name: &str) -> Option<u64>;
+    fn symbol(&self, name: &str) -> Option<u64>;
+    fn peek(&self, addr: usize) -> Result<usize>;
+}
+
+impl Target for Subordinate {
+    fn register(&self, name: &str) -> Option<u64> {
+        self.registers().get(name)
+    }
+
+    fn symbol(&self, name: &str) -> Option<u64> {
+        self.symbol(name).map(|symbol| symbol.value)
+    }
+
+    fn peek(&self, addr: usize) -> Result<usize> {
+        self.peek(addr)
+    }
+}
+
+impl Expr {
+    pub fn parse(s: &str) -> Result<Self> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            pos: 0,
+        };
+        let expr = parser.expr(0)?;
+        match parser.peek() {
+            None => Ok(expr),
+            Some(token) => Err(format!("unexpected `{}` in expression", token).into()),
+        }
+    }
+
+    pub fn eval<T: Target>(&self, subordinate: &T) -> Result<i64> {
+        match self {
+            Expr::Int(i) => Ok(*i),
+            Expr::Register(name) => match subordinate.register(name) {
+                Some(value) => Ok(value as i64),
+                None => Err(format!("unknown register `{}`", name).into()),
+            },
+            Expr::Symbol(name) => match subordinate.symbol(name) {
+                Some(value) => Ok(value as i64),
+                None => Err(format!("unknown symbol `{}`", name).into()),
+            },
+            Expr::Deref(expr) => Ok(subordinate.peek(expr.eval(subordinate)? as usize)? as i64),
+            Expr::Unary(op, expr) => {
+                let value = expr.eval(subordinate)?;
+                Ok(match op {
+                    UnOp::Neg => value.wrapping_neg(),
+                    UnOp::Not => (value == 0) as i64,
+                    UnOp::BitNot => !value,
+                })
+            }
+            Expr::Binary(BinOp::And, lhs, rhs) => {
+                Ok((lhs.eval(subordinate)? != 0 && rhs.eval(subordinate)? != 0) as i64)
+            }
+            Expr::Binary(BinOp::Or, lhs, rhs) => {
+                Ok((lhs.eval(subordinate)? != 0 || rhs.eval(subordinate)? != 0) as i64)
+            }
+            Expr::Binary(op, lhs, rhs) => {
+                let lhs = lhs.eval(subordinate)?;
+                let rhs = rhs.eval(subordinate)?;
+                Ok(match op {
+                    BinOp::Mul => lhs.wrapping_mul(rhs),
+                    BinOp::Div | BinOp::Rem if rhs == 0 => return Err("division by zero".into()),
+                    BinOp::Div => lhs.wrapping_div(rhs),
+                    BinOp::Rem => lhs.wrapping_rem(rhs),
+                    BinOp::Add => lhs.wrapping_add(rhs),
+                    BinOp::Sub => lhs.wrapping_sub(rhs),
+                    BinOp::Shl => lhs.wrapping_shl(rhs as u32),
+                    BinOp::Shr => lhs.wrapping_shr(rhs as u32),
+                    BinOp::Lt => (lhs < rhs) as i64,
+                    BinOp::Le => (lhs <= rhs) as i64,
+                    BinOp::Gt => (lhs > rhs) as i64,
+                    BinOp::Ge => (lhs >= rhs) as i64,
+                    BinOp::Eq => (lhs == rhs) as i64,
+                    BinOp::Ne => (lhs != rhs) as i64,
+                    BinOp::BitAnd => lhs & rhs,
+                    BinOp::BitXor => lhs ^ rhs,
+                    BinOp::BitOr => lhs | rhs,
+                    BinOp::And | BinOp::Or => unreachable!(),
+                })
+            }
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::Int(i) => write!(f, "{}", i),
+            Expr::Register(name) | Expr::Symbol(name) => f.write_str(name),
+            Expr::Deref(expr) | Expr::Unary(_, expr) => {
+                let op = match self {
+                    Expr::Unary(UnOp::Neg, _) => "-",
+                    Expr::Unary(UnOp::Not, _) => "!",
+                    Expr::Unary(UnOp::BitNot, _) => "~",
+                    _ => "*",
+                };
+                match expr.as_ref() {
+                    Expr::Binary(_, _, _) => write!(f, "{}({})", op, expr),
+                    _ => write!(f, "{}{}", op, expr),
+                }
+            }
+            Expr::Binary(op, lhs, rhs) => {
+                // Operators are left associative, so the right hand side
+                // needs brackets on a tie too.
+                match lhs.as_ref() {
+                    Expr::Binary(inner, _, _) if inner.precedence() < op.precedence() => {
+                        write!(f, "({})", lhs)?
+                    }
+                    _ => write!(f, "{}", lhs)?,
+                }
+                write!(f, " {} ", op.symbol())?;
+                match rhs.as_ref() {
+                    Expr::Binary(inner, _, _) if inner.precedence() <= op.precedence() => {
+                        write!(f, "({})", rhs)
+                    }
+                    _ => write!(f, "{}", rhs),
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Int(i64),
+    Ident(String),
+    Op(&'static str),
+    LParen,
+    RParen,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Int(i) => write!(f, "{}", i),
+            Token::Ident(s) => f.write_str(s),
+            Token::Op(s) => f.write_str(s),
+            Token::LParen => f.write_str("("),
+            Token::RParen => f.write_str(")"),
+        }
+    }
+}
+
+// Longest operators first, so that `<=` doesn't get read as `<` then `=`.
+const OPERATORS: &[&str] = &[
+    "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "*", "/", "%", "+", "-", "<", ">", "&", "^",
+    "|", "!", "~",
+];
+
+fn tokenize(s: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut rest = s.trim_start();
+
+    while let Some(c) = rest.chars().next() {
+        if c == '(' || c == ')' {
+            tokens.push(if c == '(' {
+                Token::LParen
+            } else {
+                Token::RParen
+            });
+            rest = &rest[1..];
+        } else if c.is_ascii_digit() {
+            let len = rest
+                .find(|c: char| !c.is_ascii_alphanumeric())
+                .unwrap_or(rest.len());
+            let literal = &rest[..len];
+            let value = match literal.strip_prefix("0x") {
+                Some(hex) => u64::from_str_radix(hex, 16)?,
+                None => literal.parse::<u64>()?,
+            };
+            tokens.push(Token::Int(value as i64));
+            rest = &rest[len..];
+        } else if c.is_alphabetic() || c == '_' || c == '$' {
+            let len = rest[c.len_utf8()..]
+                .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.'))
+                .map(|len| len + c.len_utf8())
+                .unwrap_or_else(|| rest.len());
+            tokens.push(Token::Ident(rest[..len].to_owned()));
+            rest = &rest[len..];
+        } else {
+            match OPERATORS.iter().find(|op| rest.starts_with(*op)) {
+                Some(op) => {
+                    tokens.push(Token::Op(op));
+                    rest = &rest[op.len()..];
+                }
+                None => return Err(format!("unexpected `{}` in expression", c).into()),
+            }
+        }
+        rest = rest.trim_start();
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    /// Precedence climbing, only consumes binary operators that bind at least
+    /// as tightly as `min_precedence`.
+    fn expr(&mut self, min_precedence: u8) -> Result<Expr> {
+        let mut lhs = self.unary()?;
+
+        while let Some(Token::Op(op)) = self.peek() {
+            let op = match binop(op) {
+                Some(op) if op.precedence() >= min_precedence => op,
+                _ => break,
+            };
+            self.next();
+            let rhs = self.expr(op.precedence() + 1)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        match self.next() {
+            Some(Token::Op("*")) => Ok(Expr::Deref(Box::new(self.unary()?))),
+            Some(Token::Op("-")) => Ok(Expr::Unary(UnOp::Neg, Box::new(self.unary()?))),
+            Some(Token::Op("!")) => Ok(Expr::Unary(UnOp::Not, Box::new(self.unary()?))),
+            Some(Token::Op("~")) => Ok(Expr::Unary(UnOp::BitNot, Box::new(self.unary()?))),
+            Some(Token::Int(i)) => Ok(Expr::Int(i)),
+            Some(Token::Ident(name)) => {
+                let name = name.trim_start_matches('$');
+                if Registers::is_register(name) {
+                    Ok(Expr::Register(name.to_owned()))
+                } else {
+                    Ok(Expr::Symbol(name.to_owned()))
+                }
+            }
+            Some(Token::LParen) => {
+                let expr = self.expr(0)?;
+                match self.next() {
+                    Some(Token::RParen) => Ok(expr),
+                    _ => Err("expected `)` in expression".into()),
+                }
+            }
+            Some(token) => Err(format!("unexpected `{}` in expression", token).into()),
+            None => Err("unexpected end of expression".into()),
+        }
+    }
+}
+
+fn binop(op: &str) -> Option<BinOp> {
+    Some(match op {
+        "*" => BinOp::Mul,
+        "/" => BinOp::Div,
+        "%" => BinOp::Rem,
+        "+" => BinOp::Add,
+        "-" => BinOp::Sub,
+        "<<" => BinOp::Shl,
+        ">>" => BinOp::Shr,
+        "<" => BinOp::Lt,
+        "<=" => BinOp::Le,
+        ">" => BinOp::Gt,
+        ">=" => BinOp::Ge,
+        "==" => BinOp::Eq,
+        "!=" => BinOp::Ne,
+        "&" => BinOp::BitAnd,
+        "^" => BinOp::BitXor,
+        "|" => BinOp::BitOr,
+        "&&" => BinOp::And,
+        "||" => BinOp::Or,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Fake;
+
+    impl Target for Fake {
+        fn register(&self, name: &str) -> Option<u64> {
+            match name {
+                "rdi" => Some(0x10),
+                "rsp" => Some(0x7ff0),
+                _ => None,
+            }
+        }
+
+        fn symbol(&self, name: &str) -> Option<u64> {
+            match name {
+                "counter" => Some(0x4000),
+                _ => None,
+            }
+        }
+
+        fn peek(&self, addr: usize) -> Result<usize> {
+            match addr {
+                0x4000 => Ok(7),
+                0x7ff8 => Ok(3),
+                _ => Err(format!("can't read 0x{:x}", addr).into()),
+            }
+        }
+    }
+
+    fn eval(s: &str) -> Result<i64> {
+        Expr::parse(s)?.eval(&Fake)
+    }
+
+    #[test]
+    fn operators() {
+        assert_eq!(eval("1 + 2 * 3").unwrap(), 7);
+        assert_eq!(eval("(1 + 2) * 3").unwrap(), 9);
+        assert_eq!(eval("10 - 4 - 3").unwrap(), 3);
+        assert_eq!(eval("17 / 5 + 17 % 5").unwrap(), 5);
+        assert_eq!(eval("1 << 4 >> 2").unwrap(), 4);
+        assert_eq!(eval("0xf0 & 0x3c ^ 0x1 | 0x100").unwrap(), 0x131);
+        assert_eq!(eval("-3 < 2 && 2 <= 2 && 3 > 2 && 2 >= 3").unwrap(), 0);
+        assert_eq!(eval("1 == 2 || 1 != 2").unwrap(), 1);
+        assert_eq!(eval("!0 + ~0 + -1").unwrap(), -1);
+        assert!(eval("1 / 0").is_err());
+        assert!(eval("1 % 0").is_err());
+    }
+
+    #[test]
+    fn registers_symbols_and_memory() {
+        assert_eq!(eval("rdi == 0x10 && *(rsp + 8) > 2").unwrap(), 1);
+        assert_eq!(eval("$rdi + 1").unwrap(), 0x11);
+        assert_eq!(eval("*counter").unwrap(), 7);
+        assert!(eval("missing").is_err());
+        assert!(eval("*rdi").is_err());
+    }
+
+    #[test]
+    fn display_keeps_precedence() {
+        let expr = Expr::parse("(1 + 2) * 3 - (4 - 5) - *(rsp + 8)").unwrap();
+        assert_eq!(expr.to_string(), "(1 + 2) * 3 - (4 - 5) - *(rsp + 8)");
+        let expr = Expr::parse("1 + 2 * 3").unwrap();
+        assert_eq!(expr.to_string(), "1 + 2 * 3");
+    }
+
+    #[test]
+    fn bad_input() {
+        assert!(Expr::parse("").is_err());
+        assert!(Expr::parse("1 +").is_err());
+        assert!(Expr::parse("(1").is_err());
+        assert!(Expr::parse("1 2").is_err());
+        assert!(Expr::parse("0xg").is_err());
+        assert!(Expr::parse("1 @ 2").is_err());
+    }
+
+    #[test]
+    fn multibyte_identifiers() {
+        assert!(matches!(Expr::parse("é").unwrap(), Expr::Symbol(name) if name == "é"));
+        assert!(matches!(
+            Expr::parse("ü_1 + 1").unwrap(),
+            Expr::Binary(BinOp::Add, _, _)
+        ));
+        assert!(eval("é").is_err());
+        assert!(Expr::parse("1 + ∑").is_err());
+    }
+}
@@ -0,0 +1,98 @@
+use crate::result::Result;
+use crate::sys::{chdir, disable_aslr};
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+/// How to start the program, besides its arguments and stdio. Changes take
+/// effect the next time it's started.
+#[derive(Debug, Clone)]
+pub struct Launch {
+    // Whether to start from our own environment, rather than an empty one.
+    inherit_env: bool,
+    // Variables set, or unset with None, on top of that, in order.
+    env: Vec<(OsString, Option<OsString>)>,
+    // Where to start it, rather than where we are.
+    cwd: Option<PathBuf>,
+    disable_randomization: bool,
+}
+
+impl Default for Launch {
+    fn default() -> Self {
+        Launch {
+            inherit_env: true,
+            env: Vec::new(),
+            cwd: None,
+            // The same addresses every run make breakpoints and watchpoints
+            // much easier to reason about.
+            disable_randomization: true,
+        }
+    }
+}
+
+impl Launch {
+    pub fn set_env(&mut self, name: &str, value: &str) {
+        let name = OsString::from(name);
+        self.env.retain(|(n, _)| *n != name);
+        self.env.push((name, Some(value.into())));
+    }
+
+    pub fn unset_env(&mut self, name: &str) {
+        let name = OsString::from(name);
+        self.env.retain(|(n, _)| *n != name);
+        self.env.push((name, None));
+    }
+
+    /// Starts the program with no environment variables at all.
+    pub fn clear_env(&mut self) {
+        self.inherit_env = false;
+        self.env.clear();
+    }
+
+    /// The environment the program gets, as names and values that needn't
+    /// be valid UTF-8.
+    pub fn environment(&self) -> Vec<(OsString, OsString)> {
+        let mut vars: Vec<(OsString, OsString)> = if self.inherit_env {
+            std::env::vars_os().collect()
+        } else {
+            Vec::new()
+        };
+        for (name, value) in &self.env {
+            vars.retain(|(n, _)| n != name);
+            if let Some(value) = value {
+                vars.push((name.clone(), value.clone()));
+            }
+        }
+        vars
+    }
+
+    pub fn cwd(&self) -> Option<&PathBuf> {
+        self.cwd.as_ref()
+    }
+
+    pub fn set_cwd(&mut self, cwd: PathBuf) -> Result<()> {
+        if !cwd.is_dir() {
+            return Err(format!("{}: not a directory", cwd.display()).into());
+        }
+        self.cwd = Some(cwd);
+        Ok(())
+    }
+
+    pub fn disable_randomization(&self) -> bool {
+        self.disable_randomization
+    }
+
+    pub fn set_disable_randomization(&mut self, disable: bool) {
+        self.disable_randomization = disable;
+    }
+
+    /// Called in the child, between fork and exec.
+    pub fn apply(&self) -> Result<()> {
+        if let Some(cwd) = &self.cwd {
+            chdir(cwd)?;
+        }
+        if self.disable_randomization {
+            disable_aslr()?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,118 @@
+use crate::result::Result;
+use std::fmt;
+
+/// Somewhere in the program a user can point at, as written on the command
+/// line. Resolving one to addresses is up to the `Subordinate`, as it
+/// depends on where the program was loaded.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Location {
+    /// `*0x401000` or `0x401000`.
+    Address(usize),
+    /// `main` or `main+0x14`.
+    Symbol(String, usize),
+    /// `hello.c:12`, where the file only has to match the end of the path.
+    Line(String, u64),
+}
+
+impl Location {
+    pub fn parse(s: &str) -> Result<Self> {
+        if let Some(addr) = s.strip_prefix('*') {
+            return Ok(Location::Address(parse_int(addr)?));
+        }
+        if s.starts_with("0x") {
+            return Ok(Location::Address(parse_int(s)?));
+        }
+
+        if let Some(i) = s.rfind(':') {
+            let (file, line) = (&s[..i], &s[i + 1..]);
+            if file.is_empty() {
+                return Err(format!("no file name in `{}`", s).into());
+            }
+            return Ok(Location::Line(file.to_owned(), line.parse()?));
+        }
+
+        if let Some(i) = s.find('+') {
+            let (name, offset) = (&s[..i], &s[i + 1..]);
+            return Ok(Location::Symbol(name.to_owned(), parse_int(offset)?));
+        }
+
+        Ok(Location::Symbol(s.to_owned(), 0))
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Location::Address(addr) => write!(f, "*0x{:x}", addr),
+            Location::Symbol(name, 0) => f.write_str(name),
+            Location::Symbol(name, offset) => write!(f, "{}+0x{:x}", name, offset),
+            Location::Line(file, line) => write!(f, "{}:{}", file, line),
+        }
+    }
+}
+
+fn parse_int(s: &str) -> Result<usize> {
+    match s.strip_prefix("0x") {
+        Some(hex) => Ok(usize::from_str_radix(hex, 16)?),
+        None => Ok(s.parse()?),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            Location::parse("*0x401000").unwrap(),
+            Location::Address(0x401000)
+        );
+        assert_eq!(
+            Location::parse("*4198400").unwrap(),
+            Location::Address(0x401000)
+        );
+        assert_eq!(
+            Location::parse("0x401000").unwrap(),
+            Location::Address(0x401000)
+        );
+        assert_eq!(
+            Location::parse("main").unwrap(),
+            Location::Symbol("main".into(), 0)
+        );
+        assert_eq!(
+            Location::parse("main+0x14").unwrap(),
+            Location::Symbol("main".into(), 0x14)
+        );
+        assert_eq!(
+            Location::parse("main+20").unwrap(),
+            Location::Symbol("main".into(), 20)
+        );
+        assert_eq!(
+            Location::parse("hello.c:12").unwrap(),
+            Location::Line("hello.c".into(), 12)
+        );
+        assert_eq!(
+            Location::parse("C:/src/hello.c:12").unwrap(),
+            Location::Line("C:/src/hello.c".into(), 12)
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(Location::parse(":12").is_err());
+        assert!(Location::parse("hello.c:").is_err());
+        assert!(Location::parse("hello.c:twelve").is_err());
+        assert!(Location::parse("main+").is_err());
+        assert!(Location::parse("main+0xzz").is_err());
+        assert!(Location::parse("*").is_err());
+        assert!(Location::parse("0x").is_err());
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for s in &["*0x401000", "main", "main+0x14", "hello.c:12"] {
+            assert_eq!(Location::parse(s).unwrap().to_string(), *s);
+        }
+    }
+}
@@ -1,10 +1,29 @@
 mod auxv;
+mod breakpoint;
 mod disassembler;
 mod dwarf;
+mod expr;
+mod launch;
+mod location;
 mod registers;
+pub mod signal;
+mod stop_reason;
 mod subordinate;
+pub mod syscall;
+mod terminal;
+mod thread;
+mod unwind;
 
+pub use breakpoint::{Access, Breakpoint, BreakpointKind, WatchpointHit};
 pub use disassembler::Disassembler;
-pub use dwarf::DebugInfo;
+pub use dwarf::{DebugInfo, LineInfo, Variable};
+pub use expr::Expr;
+pub use location::Location;
 pub use registers::Registers;
-pub use subordinate::Subordinate;
+pub use signal::{SignalHandling, SignalStop};
+pub use stop_reason::StopReason;
+pub use subordinate::{ForkMode, Subordinate};
+pub use syscall::SyscallStop;
+pub use terminal::{IoMode, Redirects};
+pub use thread::Thread;
+pub use unwind::Frame;
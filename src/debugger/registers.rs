@@ -100,6 +100,10 @@ impl From<Registers> for user_regs_struct {
 }
 
 impl Registers {
+    pub fn is_register(name: &str) -> bool {
+        Registers::default().get(name).is_some()
+    }
+
     pub fn get(&self, name: &str) -> Option<u64> {
         match name {
             "r15" => Some(self.r15),
@@ -132,4 +136,96 @@ impl Registers {
             _ => None,
         }
     }
+
+    /// Sets a register by name, returning false if there's no such register.
+    pub fn set(&mut self, name: &str, value: u64) -> bool {
+        let reg = match name {
+            "r15" => &mut self.r15,
+            "r14" => &mut self.r14,
+            "r13" => &mut self.r13,
+            "r12" => &mut self.r12,
+            "rbp" => &mut self.rbp,
+            "rbx" => &mut self.rbx,
+            "r11" => &mut self.r11,
+            "r10" => &mut self.r10,
+            "r9" => &mut self.r9,
+            "r8" => &mut self.r8,
+            "rax" => &mut self.rax,
+            "rcx" => &mut self.rcx,
+            "rdx" => &mut self.rdx,
+            "rsi" => &mut self.rsi,
+            "rdi" => &mut self.rdi,
+            "orig_rax" => &mut self.orig_rax,
+            "rip" => &mut self.rip,
+            "cs" => &mut self.cs,
+            "eflags" => &mut self.eflags,
+            "rsp" => &mut self.rsp,
+            "ss" => &mut self.ss,
+            "fs_base" => &mut self.fs_base,
+            "gs_base" => &mut self.gs_base,
+            "ds" => &mut self.ds,
+            "es" => &mut self.es,
+            "fs" => &mut self.fs,
+            "gs" => &mut self.gs,
+            _ => return false,
+        };
+        *reg = value;
+        true
+    }
+
+    /// The name of a register from its DWARF number on x86-64. The return
+    /// address column, 16, is rip.
+    pub fn dwarf_name(reg: u16) -> Option<&'static str> {
+        let name = match reg {
+            0 => "rax",
+            1 => "rdx",
+            2 => "rcx",
+            3 => "rbx",
+            4 => "rsi",
+            5 => "rdi",
+            6 => "rbp",
+            7 => "rsp",
+            8 => "r8",
+            9 => "r9",
+            10 => "r10",
+            11 => "r11",
+            12 => "r12",
+            13 => "r13",
+            14 => "r14",
+            15 => "r15",
+            16 => "rip",
+            49 => "eflags",
+            50 => "es",
+            51 => "cs",
+            52 => "ss",
+            53 => "ds",
+            54 => "fs",
+            55 => "gs",
+            58 => "fs_base",
+            59 => "gs_base",
+            _ => return None,
+        };
+        Some(name)
+    }
+
+    /// Whether a function has to give a register back to its caller as it
+    /// found it, which makes it the same in every frame unless unwinding
+    /// says otherwise. rip and rsp come back by way of the return.
+    pub fn callee_saved(name: &str) -> bool {
+        matches!(
+            name,
+            "rbx" | "rbp" | "rsp" | "r12" | "r13" | "r14" | "r15" | "rip"
+        )
+    }
+
+    pub fn get_dwarf(&self, reg: u16) -> Option<u64> {
+        self.get(Registers::dwarf_name(reg)?)
+    }
+
+    pub fn set_dwarf(&mut self, reg: u16, value: u64) -> bool {
+        match Registers::dwarf_name(reg) {
+            Some(name) => self.set(name, value),
+            None => false,
+        }
+    }
 }
@@ -0,0 +1,266 @@
+use crate::sys::ptrace::SI_KERNEL;
+use libc::{
+    SIGABRT, SIGALRM, SIGBUS, SIGCHLD, SIGCONT, SIGFPE, SIGHUP, SIGILL, SIGINT, SIGIO, SIGKILL,
+    SIGPIPE, SIGPROF, SIGPWR, SIGQUIT, SIGSEGV, SIGSTKFLT, SIGSTOP, SIGSYS, SIGTERM, SIGTRAP,
+    SIGTSTP, SIGTTIN, SIGTTOU, SIGURG, SIGUSR1, SIGUSR2, SIGVTALRM, SIGWINCH, SIGXCPU, SIGXFSZ,
+};
+use std::fmt;
+
+/// The highest realtime signal, signals go from 1 up to this.
+pub const NSIG: i32 = 64;
+
+// si_code values for signals sent by kill(2), sigqueue(3) and tgkill(2).
+const SI_USER: i32 = 0;
+const SI_QUEUE: i32 = -1;
+const SI_TKILL: i32 = -6;
+
+/// What to do when the subordinate gets a signal.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SignalHandling {
+    /// Stop and give control back to the user.
+    pub stop: bool,
+    /// Say that the signal arrived, even if we carry on.
+    pub print: bool,
+    /// Let the subordinate see the signal when it's resumed.
+    pub pass: bool,
+}
+
+impl SignalHandling {
+    /// What gdb does by default.
+    pub fn default_for(signal: i32) -> Self {
+        match signal {
+            // These are how we get control, the subordinate doesn't want them.
+            SIGINT | SIGTRAP => SignalHandling {
+                stop: true,
+                print: true,
+                pass: false,
+            },
+            // Passing a SIGSTOP on would stop the subordinate again, which we'd
+            // see as another SIGSTOP.
+            SIGSTOP => SignalHandling {
+                stop: true,
+                print: true,
+                pass: false,
+            },
+            // Too common to be interesting.
+            SIGALRM | SIGURG | SIGCHLD | SIGWINCH | SIGIO | SIGVTALRM | SIGPROF => SignalHandling {
+                stop: false,
+                print: false,
+                pass: true,
+            },
+            _ => SignalHandling {
+                stop: true,
+                print: true,
+                pass: true,
+            },
+        }
+    }
+}
+
+/// A signal the subordinate has received, from PTRACE_GETSIGINFO.
+#[derive(Debug, Clone)]
+pub struct SignalStop {
+    pub signal: i32,
+    /// si_code, i.e. why the signal was sent.
+    pub code: i32,
+    /// The address that faulted, for SIGSEGV, SIGBUS, SIGILL and SIGFPE.
+    pub addr: Option<usize>,
+}
+
+impl SignalStop {
+    pub fn from_siginfo(siginfo: &libc::siginfo_t) -> Self {
+        let addr = match siginfo.si_signo {
+            SIGSEGV | SIGBUS | SIGILL | SIGFPE if siginfo.si_code > 0 => {
+                Some(unsafe { siginfo.si_addr() } as usize)
+            }
+            _ => None,
+        };
+        SignalStop {
+            signal: siginfo.si_signo,
+            code: siginfo.si_code,
+            addr,
+        }
+    }
+
+    /// The symbolic name of `code`, e.g. SEGV_MAPERR.
+    pub fn code_name(&self) -> Option<&'static str> {
+        let name = match (self.signal, self.code) {
+            (_, SI_USER) => "SI_USER",
+            (_, SI_KERNEL) => "SI_KERNEL",
+            (_, SI_QUEUE) => "SI_QUEUE",
+            (_, SI_TKILL) => "SI_TKILL",
+            (SIGSEGV, 1) => "SEGV_MAPERR",
+            (SIGSEGV, 2) => "SEGV_ACCERR",
+            (SIGSEGV, 3) => "SEGV_BNDERR",
+            (SIGSEGV, 4) => "SEGV_PKUERR",
+            (SIGBUS, 1) => "BUS_ADRALN",
+            (SIGBUS, 2) => "BUS_ADRERR",
+            (SIGBUS, 3) => "BUS_OBJERR",
+            (SIGILL, 1) => "ILL_ILLOPC",
+            (SIGILL, 2) => "ILL_ILLOPN",
+            (SIGILL, 3) => "ILL_ILLADR",
+            (SIGILL, 4) => "ILL_ILLTRP",
+            (SIGILL, 5) => "ILL_PRVOPC",
+            (SIGILL, 6) => "ILL_PRVREG",
+            (SIGILL, 7) => "ILL_COPROC",
+            (SIGILL, 8) => "ILL_BADSTK",
+            (SIGFPE, 1) => "FPE_INTDIV",
+            (SIGFPE, 2) => "FPE_INTOVF",
+            (SIGFPE, 3) => "FPE_FLTDIV",
+            (SIGFPE, 4) => "FPE_FLTOVF",
+            (SIGFPE, 5) => "FPE_FLTUND",
+            (SIGFPE, 6) => "FPE_FLTRES",
+            (SIGFPE, 7) => "FPE_FLTINV",
+            (SIGFPE, 8) => "FPE_FLTSUB",
+            (SIGCHLD, 1) => "CLD_EXITED",
+            (SIGCHLD, 2) => "CLD_KILLED",
+            (SIGCHLD, 3) => "CLD_DUMPED",
+            (SIGCHLD, 4) => "CLD_TRAPPED",
+            (SIGCHLD, 5) => "CLD_STOPPED",
+            (SIGCHLD, 6) => "CLD_CONTINUED",
+            _ => return None,
+        };
+        Some(name)
+    }
+}
+
+impl fmt::Display for SignalStop {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}, {}", name(self.signal), description(self.signal))?;
+        match self.code_name() {
+            Some(code) => write!(f, " ({}", code)?,
+            None => write!(f, " (si_code {}", self.code)?,
+        }
+        if let Some(addr) = self.addr {
+            write!(f, ", address 0x{:x}", addr)?;
+        }
+        f.write_str(")")
+    }
+}
+
+/// The name of a signal, e.g. SIGSEGV. Realtime signals are just numbered.
+pub fn name(signal: i32) -> String {
+    match SIGNALS.iter().find(|s| s.0 == signal) {
+        Some(s) => s.1.to_owned(),
+        None => format!("SIG{}", signal),
+    }
+}
+
+/// Looks up a signal by name, with or without the SIG, or by number.
+pub fn number(name: &str) -> Option<i32> {
+    let upper = name.to_uppercase();
+    let name = upper.strip_prefix("SIG").unwrap_or(&upper);
+    let signal = match SIGNALS.iter().find(|s| &s.1[3..] == name) {
+        Some(s) => s.0,
+        None => name.parse().ok()?,
+    };
+    if signal > 0 && signal <= NSIG {
+        Some(signal)
+    } else {
+        None
+    }
+}
+
+pub fn description(signal: i32) -> &'static str {
+    SIGNALS
+        .iter()
+        .find(|s| s.0 == signal)
+        .map_or("Real-time signal", |s| s.2)
+}
+
+const SIGNALS: &[(i32, &str, &str)] = &[
+    (SIGHUP, "SIGHUP", "Hangup"),
+    (SIGINT, "SIGINT", "Interrupt"),
+    (SIGQUIT, "SIGQUIT", "Quit"),
+    (SIGILL, "SIGILL", "Illegal instruction"),
+    (SIGTRAP, "SIGTRAP", "Trace/breakpoint trap"),
+    (SIGABRT, "SIGABRT", "Aborted"),
+    (SIGBUS, "SIGBUS", "Bus error"),
+    (SIGFPE, "SIGFPE", "Arithmetic exception"),
+    (SIGKILL, "SIGKILL", "Killed"),
+    (SIGUSR1, "SIGUSR1", "User defined signal 1"),
+    (SIGSEGV, "SIGSEGV", "Segmentation fault"),
+    (SIGUSR2, "SIGUSR2", "User defined signal 2"),
+    (SIGPIPE, "SIGPIPE", "Broken pipe"),
+    (SIGALRM, "SIGALRM", "Alarm clock"),
+    (SIGTERM, "SIGTERM", "Terminated"),
+    (SIGSTKFLT, "SIGSTKFLT", "Stack fault"),
+    (SIGCHLD, "SIGCHLD", "Child status changed"),
+    (SIGCONT, "SIGCONT", "Continued"),
+    (SIGSTOP, "SIGSTOP", "Stopped (signal)"),
+    (SIGTSTP, "SIGTSTP", "Stopped (user)"),
+    (SIGTTIN, "SIGTTIN", "Stopped (tty input)"),
+    (SIGTTOU, "SIGTTOU", "Stopped (tty output)"),
+    (SIGURG, "SIGURG", "Urgent I/O condition"),
+    (SIGXCPU, "SIGXCPU", "CPU time limit exceeded"),
+    (SIGXFSZ, "SIGXFSZ", "File size limit exceeded"),
+    (SIGVTALRM, "SIGVTALRM", "Virtual timer expired"),
+    (SIGPROF, "SIGPROF", "Profiling timer expired"),
+    (SIGWINCH, "SIGWINCH", "Window size changed"),
+    (SIGIO, "SIGIO", "I/O possible"),
+    (SIGPWR, "SIGPWR", "Power fail/restart"),
+    (SIGSYS, "SIGSYS", "Bad system call"),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_and_numbers() {
+        assert_eq!(name(SIGSEGV), "SIGSEGV");
+        assert_eq!(name(40), "SIG40");
+        assert_eq!(number("SIGSEGV"), Some(SIGSEGV));
+        assert_eq!(number("segv"), Some(SIGSEGV));
+        assert_eq!(number("sigint"), Some(SIGINT));
+        assert_eq!(number("40"), Some(40));
+        assert_eq!(number("0"), None);
+        assert_eq!(number("65"), None);
+        assert_eq!(number("SIGNOPE"), None);
+        for &(signal, name, _) in SIGNALS {
+            assert_eq!(number(name), Some(signal));
+        }
+    }
+
+    #[test]
+    fn descriptions() {
+        assert_eq!(description(SIGSEGV), "Segmentation fault");
+        assert_eq!(description(40), "Real-time signal");
+    }
+
+    #[test]
+    fn default_handling() {
+        assert!(!SignalHandling::default_for(SIGTRAP).pass);
+        assert!(!SignalHandling::default_for(SIGALRM).stop);
+        assert!(SignalHandling::default_for(SIGSEGV).pass);
+        assert!(SignalHandling::default_for(40).stop);
+    }
+
+    #[test]
+    fn display() {
+        let stop = SignalStop {
+            signal: SIGSEGV,
+            code: 1,
+            addr: Some(0x10),
+        };
+        assert_eq!(
+            stop.to_string(),
+            "SIGSEGV, Segmentation fault (SEGV_MAPERR, address 0x10)"
+        );
+        let stop = SignalStop {
+            signal: SIGUSR1,
+            code: SI_TKILL,
+            addr: None,
+        };
+        assert_eq!(
+            stop.to_string(),
+            "SIGUSR1, User defined signal 1 (SI_TKILL)"
+        );
+        let stop = SignalStop {
+            signal: SIGSEGV,
+            code: 99,
+            addr: None,
+        };
+        assert_eq!(stop.to_string(), "SIGSEGV, Segmentation fault (si_code 99)");
+    }
+}
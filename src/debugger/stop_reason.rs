@@ -0,0 +1,62 @@
+use crate::debugger::{signal, SignalStop, SyscallStop, WatchpointHit};
+use std::fmt;
+
+/// Why the subordinate stopped, after being resumed.
+#[derive(Debug, Clone)]
+pub enum StopReason {
+    /// One or more breakpoints, by ID.
+    Breakpoint(Vec<usize>),
+    Watchpoint(Vec<WatchpointHit>),
+    /// A syscall catchpoint, with the IDs of the catchpoints that fired.
+    Syscall(Vec<usize>, SyscallStop),
+    Signal(SignalStop),
+    /// Got where we were going, after a single step or stepping a line.
+    Step,
+    /// The process exited, with this status.
+    Exited(i32),
+    /// The process was killed by this signal.
+    Killed(i32),
+}
+
+impl StopReason {
+    /// Whether the process is gone.
+    pub fn is_exit(&self) -> bool {
+        matches!(self, StopReason::Exited(_) | StopReason::Killed(_))
+    }
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StopReason::Breakpoint(ids) => {
+                let ids: Vec<String> = ids.iter().map(usize::to_string).collect();
+                let plural = if ids.len() > 1 { "s" } else { "" };
+                write!(f, "breakpoint{} {} hit", plural, ids.join(", "))
+            }
+            StopReason::Watchpoint(hits) => {
+                let ids: Vec<String> = hits.iter().map(|hit| hit.id.to_string()).collect();
+                let plural = if ids.len() > 1 { "s" } else { "" };
+                write!(f, "watchpoint{} {} triggered", plural, ids.join(", "))
+            }
+            StopReason::Syscall(ids, stop) => {
+                let ids: Vec<String> = ids.iter().map(usize::to_string).collect();
+                let event = if stop.is_entry() {
+                    "call to"
+                } else {
+                    "returned from"
+                };
+                write!(
+                    f,
+                    "catchpoint {}, {} syscall {}",
+                    ids.join(", "),
+                    event,
+                    stop
+                )
+            }
+            StopReason::Signal(stop) => write!(f, "received signal {}", stop),
+            StopReason::Step => f.write_str("step complete"),
+            StopReason::Exited(status) => write!(f, "exited with status {}", status),
+            StopReason::Killed(sig) => write!(f, "killed by signal {}", signal::name(*sig)),
+        }
+    }
+}
@@ -1,112 +1,570 @@
 use crate::debugger::{
     auxv::{self, Entry::*},
-    DebugInfo, Registers,
+    dwarf::{Context, LineInfo},
+    launch::Launch,
+    signal::NSIG,
+    terminal::{Stdio, Terminal},
+    unwind::{self, Frame, Modules},
+    Access, Breakpoint, BreakpointKind, DebugInfo, Disassembler, Expr, IoMode, Location, Redirects,
+    Registers, SignalHandling, SignalStop, StopReason, SyscallStop, Thread, Variable,
+    WatchpointHit,
 };
 
+use crate::error::Error;
 use crate::result::Result;
 use crate::sys::{Fork::*, WaitStatus::*, *};
+use iced_x86::{FlowControl, Instruction};
+use libc::{
+    __WALL, PTRACE_EVENT_CLONE, PTRACE_EVENT_EXEC, PTRACE_EVENT_FORK, PTRACE_EVENT_VFORK,
+    PTRACE_EVENT_VFORK_DONE, PTRACE_O_TRACECLONE, PTRACE_O_TRACEEXEC, PTRACE_O_TRACEFORK,
+    PTRACE_O_TRACESYSGOOD, PTRACE_O_TRACEVFORK, PTRACE_O_TRACEVFORKDONE, SIGKILL, SIGSTOP, SIGTRAP,
+};
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::fs::File;
+use std::io::Write;
+use std::path::Path;
 
 use elf;
 
+const PTRACE_OPTIONS: i32 = PTRACE_O_TRACECLONE
+    | PTRACE_O_TRACEFORK
+    | PTRACE_O_TRACEVFORK
+    | PTRACE_O_TRACEVFORKDONE
+    | PTRACE_O_TRACEEXEC
+    | PTRACE_O_TRACESYSGOOD;
+
+/// Which side of a fork we carry on debugging.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ForkMode {
+    Parent,
+    Child,
+}
+
 pub struct Subordinate {
+    // The program and arguments to start it with again, for `restart`.
+    cmd: Vec<String>,
+    launch: Launch,
+    redirects: Redirects,
+    // Our end of the subordinate's stdio.
+    terminal: Terminal,
+    // What the terminal should be the next time we start the program.
+    io_mode: IoMode,
     pid: i32,
+    // The thread that stepping, registers and the stack refer to.
+    tid: i32,
+    threads: Vec<Thread>,
+    next_thread_id: usize,
+    // Whether all threads were resumed, rather than just the current one
+    // being stepped, so that new threads know whether to carry on too.
+    all_running: bool,
+    // The current thread's registers.
     registers: Registers,
     stack: Vec<usize>,
     wait_status: WaitStatus,
-    breakpoints: HashMap<usize, usize>,
+    // IDs of the breakpoints that triggered the current stop, if it was
+    // caused by a breakpoint at all.
+    breakpoints_hit: Option<Vec<usize>>,
+    watchpoints_hit: Vec<WatchpointHit>,
+    syscall_stop: Option<SyscallStop>,
+    signal_stop: Option<SignalStop>,
+    // Signals that arrived and were printed but didn't stop us, since the
+    // front end last asked for them.
+    noticed_signals: Vec<SignalStop>,
+    // What to do with each signal, indexed by signal number.
+    signals: Vec<SignalHandling>,
+    breakpoints: Vec<Breakpoint>,
+    next_breakpoint_id: usize,
+    // Addresses we've written an int3 to, and the byte that was there before.
+    inserted: HashMap<usize, u8>,
+    // IDs of the breakpoints occupying DR0-DR3.
+    debug_registers: [Option<usize>; 4],
     debug_info: DebugInfo,
     auxv: Vec<auxv::Entry>,
     symbols: Vec<elf::types::Symbol>,
+    // The level of the frame selected on the current thread's stack.
+    frame: usize,
+    // The files mapped into the process, for unwinding the stack. Loaded
+    // lazily, hence the RefCell so that looking at the stack needn't be a
+    // change.
+    modules: RefCell<Modules>,
+    // How far the executable was moved from the addresses in the ELF file
+    // when it was loaded, non-zero for PIE.
+    load_bias: u64,
+    // The size of the executable's thread-local storage block, if it has
+    // one, to find its thread-local variables.
+    tls_size: Option<usize>,
+    // Breakpoints we set for ourselves while stepping, along with the lowest
+    // stack pointer they count at so that recursive calls don't stop us early.
+    internal_breakpoints: Vec<(usize, u64)>,
+    // Whether we attached to a process that was already running, rather
+    // than starting it ourselves.
+    attached: bool,
+    detached: bool,
+    follow_fork_mode: ForkMode,
+    // Whether to let go of the side of a fork we aren't following, rather
+    // than keeping it stopped.
+    detach_on_fork: bool,
+    // Threads of the processes we've kept stopped after a fork, by process.
+    held: Vec<Vec<i32>>,
+    // The threads of a parent we followed the vfork child of. It shares
+    // memory, breakpoints included, with the child until the child execs.
+    vfork_parent: Option<Vec<i32>>,
 }
 
 impl Subordinate {
-    pub fn spawn(cmd: Vec<String>) -> Result<Self> {
+    pub fn spawn(mut cmd: Vec<String>, io_mode: IoMode) -> Result<Self> {
+        // So that it's the same program if the working directory changes.
+        if cmd.first().is_some_and(|exe| exe.contains('/')) {
+            cmd[0] = std::fs::canonicalize(&cmd[0])?
+                .to_string_lossy()
+                .into_owned();
+        }
+        let terminal = Terminal::new(io_mode)?;
+        let stdio = terminal.stdio(&Redirects::default())?;
+        let pid = Subordinate::fork_exec(&cmd, &stdio, &Launch::default())?;
+        Subordinate::new(cmd, pid, false, terminal)
+    }
+
+    /// Starts tracing a process that's already running. It gets stopped
+    /// wherever it happens to be.
+    pub fn attach(pid: i32) -> Result<Self> {
+        info!("attaching to pid: {}", pid);
+
+        // Enough to start it again with `restart`, if it comes to that.
+        let exe = std::fs::read_link(format!("/proc/{}/exe", pid))?;
+        let cmdline = std::fs::read(format!("/proc/{}/cmdline", pid))?;
+        let mut cmd = vec![exe.to_string_lossy().into_owned()];
+        cmd.extend(
+            cmdline
+                .split(|b| *b == 0)
+                .skip(1)
+                .filter(|arg| !arg.is_empty())
+                .map(|arg| String::from_utf8_lossy(arg).into_owned()),
+        );
+
+        ptrace::attach(pid)?;
+        // It already has somewhere to write to.
+        let terminal = Terminal::new(IoMode::Inherit)?;
+        Subordinate::new(cmd, pid, true, terminal)
+    }
+
+    /// Starts the program, stopped on its first instruction.
+    fn fork_exec(cmd: &Vec<String>, stdio: &Stdio, launch: &Launch) -> Result<i32> {
         if cmd.len() == 0 {
             return Err("empty command given".into());
         }
 
         info!("spawning with cmd: {:?}", cmd);
 
+        let env = launch.environment();
         let pid = match fork()? {
             Parent(child_pid) => child_pid,
             Child => {
-                ptrace::traceme()?;
-                execvp(&cmd)?;
-                0
+                // This is the program now, whatever goes wrong it mustn't
+                // carry on as a second copy of the debugger.
+                let exec = launch
+                    .apply()
+                    .and_then(|_| stdio.install())
+                    .and_then(|_| ptrace::traceme())
+                    .and_then(|_| execvpe(cmd, &env));
+                match exec {
+                    Err(Error::Errno(errno)) => {
+                        let e = std::io::Error::from_raw_os_error(errno);
+                        eprintln!("{}: {}", cmd[0], e);
+                    }
+                    Err(e) => eprintln!("{}: {}", cmd[0], e),
+                    Ok(()) => {}
+                }
+                unsafe { libc::_exit(127) }
             }
         };
+        Ok(pid)
+    }
 
-        let elf = elf::File::open_path(&cmd[0])?;
-        let debug_info = DebugInfo::new(File::open(&cmd[0])?)?;
-
-        let mut symbols: Vec<elf::types::Symbol> = Vec::new();
-        if let Some(section) = elf.get_section(".symtab") {
-            symbols = elf.get_symbols(section)?;
-        }
+    fn new(cmd: Vec<String>, pid: i32, attached: bool, terminal: Terminal) -> Result<Self> {
+        let exe = if attached {
+            std::fs::read_link(format!("/proc/{}/exe", pid))?
+        } else {
+            Path::new(&cmd[0]).to_path_buf()
+        };
+        let (entry, debug_info, symbols, tls_size) = Subordinate::load(&exe)?;
 
         let mut subordinate = Subordinate {
+            cmd,
+            launch: Launch::default(),
+            redirects: Redirects::default(),
+            io_mode: terminal.mode(),
+            terminal,
             pid,
+            tid: pid,
+            threads: vec![Thread::new(1, pid)],
+            next_thread_id: 2,
+            all_running: false,
             wait_status: WaitStatus::Unknwon(0, 0),
+            breakpoints_hit: None,
+            watchpoints_hit: Vec::new(),
+            syscall_stop: None,
+            signal_stop: None,
+            noticed_signals: Vec::new(),
+            signals: (0..=NSIG).map(SignalHandling::default_for).collect(),
             registers: Registers::default(),
             stack: Vec::new(),
-            breakpoints: HashMap::new(),
+            breakpoints: Vec::new(),
+            next_breakpoint_id: 1,
+            inserted: HashMap::new(),
+            debug_registers: [None; 4],
             debug_info,
             auxv: Vec::new(),
             symbols,
+            frame: 0,
+            modules: RefCell::default(),
+            load_bias: 0,
+            tls_size,
+            internal_breakpoints: Vec::new(),
+            attached,
+            detached: false,
+            follow_fork_mode: ForkMode::Parent,
+            detach_on_fork: true,
+            held: Vec::new(),
+            vfork_parent: None,
         };
 
         subordinate.fetch_state()?;
+        // The SIGTRAP we get after exec isn't one for the program.
+        subordinate.signal_stop = None;
+        subordinate.start_thread(0)?;
+        if attached {
+            subordinate.attach_threads()?;
+        }
+
+        // We catch spawned processes on their first instruction, with the
+        // auxiliary vector still sat on the stack. By the time we attach to
+        // one it could be anywhere.
+        let auxv = if attached {
+            auxv::read_proc(pid)?
+        } else {
+            auxv::read(&subordinate)?
+        };
+        subordinate.relocate(entry, auxv);
+
+        Ok(subordinate)
+    }
+
+    /// Kills the current process, if there is one, and starts the program
+    /// again, stopped on its first instruction. `args` replace the arguments
+    /// it was started with last time, if given. Breakpoints carry over.
+    pub fn restart(&mut self, args: Option<Vec<String>>) -> Result<()> {
+        // It isn't ours to kill without being told to.
+        if self.attached && self.is_alive() {
+            return Err("the program was attached to, kill it first to start it again".into());
+        }
+        if self.io_mode != self.terminal.mode() {
+            self.terminal.reopen(self.io_mode)?;
+        }
+        // Before killing anything, in case the files can't be opened.
+        let stdio = self.terminal.stdio(&self.redirects)?;
+        if self.is_alive() {
+            self.kill()?;
+        }
+        if let Some(args) = args {
+            self.cmd.truncate(1);
+            self.cmd.extend(args);
+        }
+
+        let pid = Subordinate::fork_exec(&self.cmd, &stdio, &self.launch)?;
+        self.pid = pid;
+        self.tid = pid;
+        self.threads = vec![Thread::new(1, pid)];
+        self.next_thread_id = 2;
+        self.all_running = false;
+        self.wait_status = WaitStatus::Unknwon(0, 0);
+        self.noticed_signals.clear();
+        self.inserted.clear();
+        self.internal_breakpoints.clear();
+        self.attached = false;
+
+        // The program may not be the one we were looking at last, if we
+        // followed an exec.
+        let (entry, debug_info, symbols, tls_size) = Subordinate::load(Path::new(&self.cmd[0]))?;
+        self.debug_info = debug_info;
+        self.symbols = symbols;
+        self.tls_size = tls_size;
+        // In case any of it was rebuilt.
+        self.modules.borrow_mut().clear();
 
-        let auxv = auxv::read(&subordinate)?;
-        for entry in &auxv {
-            match entry {
-                EntryAddr(addr) => {
-                    let amount = *addr as u64 - elf.ehdr.entry;
-                    subordinate.shift_symbols(amount);
+        self.fetch_state()?;
+        self.signal_stop = None;
+        self.start_thread(0)?;
+        let auxv = auxv::read(self)?;
+        self.relocate(entry, auxv);
+        self.rebind_breakpoints(false)
+    }
+
+    /// Kills the process and waits for it to be gone.
+    pub fn kill(&mut self) -> Result<()> {
+        self.check_alive()?;
+        self.release_held()?;
+
+        info!("killing process {}", self.pid);
+        kill(self.pid, SIGKILL)?;
+        // Each thread tells us it died, the main thread last.
+        loop {
+            let status = waitpid(-1, __WALL)?;
+            match status {
+                Exited(pid, _) | Signaled(pid, _) if pid == self.pid => {
+                    self.wait_status = status;
                     break;
                 }
                 _ => {}
             }
         }
+        self.threads.clear();
+        Ok(())
+    }
+
+    fn check_alive(&self) -> Result<()> {
+        if self.is_alive() {
+            Ok(())
+        } else {
+            Err("the program is not being run".into())
+        }
+    }
 
-        subordinate.auxv = auxv;
+    /// Reads the symbols and debug info of an executable, along with the
+    /// entry point it was linked with and the size of its TLS block.
+    fn load(exe: &Path) -> Result<(u64, DebugInfo, Vec<elf::types::Symbol>, Option<usize>)> {
+        let elf = elf::File::open_path(exe)?;
+        let debug_info = DebugInfo::new(File::open(exe)?)?;
 
-        Ok(subordinate)
+        let mut symbols: Vec<elf::types::Symbol> = Vec::new();
+        if let Some(section) = elf.get_section(".symtab") {
+            symbols = elf.get_symbols(section)?;
+        }
+
+        // The block is rounded up to its alignment, so that the thread
+        // pointer right after it is aligned too.
+        let tls_size = elf
+            .phdrs
+            .iter()
+            .find(|phdr| phdr.progtype == elf::types::PT_TLS)
+            .map(|phdr| {
+                let align = phdr.align.max(1);
+                ((phdr.memsz + align - 1) & !(align - 1)) as usize
+            });
+
+        Ok((elf.ehdr.entry, debug_info, symbols, tls_size))
     }
 
-    pub fn step(&mut self) -> Result<()> {
-        ptrace::singlestep(self.pid)?;
-        self.fetch_state()?;
-        Ok(())
+    /// Works out how far the program was moved when it was loaded from its
+    /// real entry point, and shifts the symbols to match.
+    fn relocate(&mut self, entry: u64, auxv: Vec<auxv::Entry>) {
+        self.load_bias = 0;
+        for aux in &auxv {
+            if let EntryAddr(addr) = aux {
+                let amount = *addr as u64 - entry;
+                self.shift_symbols(amount);
+                self.load_bias = amount;
+                break;
+            }
+        }
+
+        self.auxv = auxv;
     }
 
-    pub fn cont(&mut self) -> Result<()> {
-        ptrace::cont(self.pid)?;
-        self.fetch_state()?;
-        Ok(())
+    /// Executes a single instruction.
+    pub fn stepi(&mut self) -> Result<StopReason> {
+        self.check_alive()?;
+        if self.step_over_breakpoint()? {
+            self.update_state()?;
+        } else {
+            self.step_thread()?;
+            self.fetch_state()?;
+        }
+        self.check_breakpoints()?;
+        Ok(self.stop_reason())
     }
 
-    pub fn peek(&self, addr: usize) -> Result<usize> {
-        ptrace::peek(self.pid, addr)
+    pub fn cont(&mut self) -> Result<StopReason> {
+        self.check_alive()?;
+        loop {
+            if self.step_over_breakpoint()? {
+                self.update_state()?;
+                // The step may have landed somewhere interesting, or the process
+                // may have died, in which case we don't want to carry on.
+                if !self.stopped_by(SIGTRAP)
+                    || self.breakpoints_hit.is_some()
+                    || self.signal_stop.is_some()
+                {
+                    if self.should_stop()? {
+                        return Ok(self.stop_reason());
+                    }
+                    continue;
+                }
+            }
+
+            self.step_threads_over_breakpoints()?;
+            if self.resume_all()? {
+                self.fetch_state()?;
+            } else {
+                self.update_state()?;
+            }
+
+            if self.should_stop()? {
+                return Ok(self.stop_reason());
+            }
+        }
     }
 
-    pub fn poke(&self, addr: usize, data: usize) -> Result<()> {
-        ptrace::poke(self.pid, addr, data)
+    /// Executes one instruction, unless it's a call in which case we run
+    /// until it returns.
+    pub fn nexti(&mut self) -> Result<StopReason> {
+        self.check_alive()?;
+        self.step_over_calls()?;
+        Ok(self.stop_reason())
+    }
+
+    /// Runs until we reach the start of a different source line, stopping
+    /// in any function called along the way that we have line info for.
+    pub fn step(&mut self) -> Result<StopReason> {
+        self.step_line(true)?;
+        Ok(self.stop_reason())
+    }
+
+    /// Runs until we reach the start of a different source line, without
+    /// descending into any functions called along the way.
+    pub fn next(&mut self) -> Result<StopReason> {
+        self.step_line(false)?;
+        Ok(self.stop_reason())
+    }
+
+    fn step_line(&mut self, into_calls: bool) -> Result<()> {
+        self.check_alive()?;
+        let start = match self.line_for_pc(self.registers.rip as usize) {
+            Some(line) => line.clone(),
+            None if into_calls => return self.stepi().map(|_| ()),
+            None => return self.nexti().map(|_| ()),
+        };
+
+        loop {
+            let stepped = if into_calls {
+                self.step_into_calls()?
+            } else {
+                self.step_over_calls()?
+            };
+            if !stepped {
+                return Ok(());
+            }
+
+            let rip = self.registers.rip as usize;
+            if into_calls && self.symbol_for_addr(rip).map(|s| s.value as usize) == Some(rip) {
+                // We've just arrived in a function worth stopping in.
+                return Ok(());
+            }
+
+            match self.line_for_pc(rip) {
+                // We've wandered somewhere without line information, most
+                // likely by returning into the C runtime.
+                None => return Ok(()),
+                Some(line) if !line.same_line(&start) && self.at_line_start(rip) => {
+                    return Ok(());
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Like `next`, but never stops on a jump backwards, so it can be used to
+    /// get out of a loop. Stops if the current function returns.
+    pub fn until(&mut self) -> Result<StopReason> {
+        self.check_alive()?;
+        let start_rip = self.registers.rip as usize;
+        let start = match self.line_for_pc(start_rip) {
+            Some(line) => line.clone(),
+            None => return self.nexti(),
+        };
+        let cfa = self.return_address().ok().map(|(_, cfa)| cfa);
+
+        loop {
+            if !self.step_over_calls()? {
+                break;
+            }
+
+            if cfa.is_some_and(|cfa| self.registers.rsp >= cfa) {
+                break;
+            }
+
+            let rip = self.registers.rip as usize;
+            match self.line_for_pc(rip) {
+                None => break,
+                Some(line)
+                    if rip > start_rip && !line.same_line(&start) && self.at_line_start(rip) =>
+                {
+                    break;
+                }
+                _ => {}
+            }
+        }
+        Ok(self.stop_reason())
+    }
+
+    /// Runs until we reach `location` in the current function, or the
+    /// current function returns. Returns whether we got to `location`.
+    pub fn until_location(&mut self, location: &Location) -> Result<bool> {
+        let rsp = self.registers.rsp;
+        self.run_to_or_return(location, rsp)
+    }
+
+    /// Runs until we reach `location` in any function, or the current
+    /// function returns. Returns whether we got to `location`.
+    pub fn advance(&mut self, location: &Location) -> Result<bool> {
+        self.run_to_or_return(location, 0)
+    }
+
+    fn run_to_or_return(&mut self, location: &Location, min_rsp: u64) -> Result<bool> {
+        self.check_alive()?;
+        let addrs = self.resolve(location)?;
+        let mut targets: Vec<(usize, u64)> = addrs.iter().map(|addr| (*addr, min_rsp)).collect();
+        if let Ok(frame_return) = self.return_address() {
+            targets.push(frame_return);
+        }
+
+        self.run_to(targets)?;
+        let rip = self.registers.rip as usize;
+        Ok(addrs.contains(&rip) && self.registers.rsp >= min_rsp)
+    }
+
+    /// Runs until the current function returns to its caller. Returns
+    /// whether it did, rather than something else stopping us first.
+    pub fn finish(&mut self) -> Result<bool> {
+        self.check_alive()?;
+        let (addr, cfa) = self.return_address()?;
+        self.run_to(vec![(addr, cfa)])
+    }
+
+    pub fn peek(&self, addr: usize) -> Result<usize> {
+        ptrace::peek(self.tid, addr)
     }
 
     pub fn read_bytes(&self, from: usize, size: usize) -> Result<Vec<u8>> {
         let mut bytes = Vec::with_capacity(size);
         let wordlen = std::mem::size_of::<usize>();
-        for i in 0..(size / wordlen) + 1 {
-            for byte in self.peek(from + wordlen * i)?.to_ne_bytes().iter() {
-                bytes.push(*byte);
-                if bytes.len() == size {
-                    break;
-                }
+        let mut addr = from;
+        while bytes.len() < size {
+            let word = self.peek(addr)?.to_ne_bytes();
+            let wanted = std::cmp::min(wordlen, size - bytes.len());
+            bytes.extend_from_slice(&word[..wanted]);
+            addr += wordlen;
+        }
+
+        // Hide our own int3s from anyone reading memory, they want to see
+        // the program as it was compiled.
+        for (addr, orig) in &self.inserted {
+            if *addr >= from && *addr < from + bytes.len() {
+                bytes[*addr - from] = *orig;
             }
         }
+
         Ok(bytes)
     }
 
@@ -119,81 +577,1510 @@ impl Subordinate {
         Ok(words)
     }
 
+    /// Takes all of our breakpoints out of the subordinate and lets it run
+    /// on without us. Nothing else can be done with it afterwards.
+    pub fn detach(&mut self) -> Result<()> {
+        self.check_alive()?;
+        let tids: Vec<i32> = self.threads.iter().map(|thread| thread.tid).collect();
+        self.release(&tids)?;
+        self.release_held()?;
+
+        self.inserted.clear();
+        self.debug_registers = [None; 4];
+        self.detached = true;
+        Ok(())
+    }
+
+    /// Lets go of every process we kept hold of after a fork.
+    pub fn release_held(&mut self) -> Result<()> {
+        if let Some(parent) = self.vfork_parent.take() {
+            self.held.push(parent);
+        }
+        for tids in std::mem::take(&mut self.held) {
+            self.release(&tids)?;
+        }
+        Ok(())
+    }
+
+    /// Takes our breakpoints out of the process the given threads belong to,
+    /// which still has them from before a fork, and detaches from it.
+    fn release(&self, tids: &[i32]) -> Result<()> {
+        if let Some(tid) = tids.first() {
+            self.write_breakpoint_sites(*tid, false)?;
+        }
+        for tid in tids {
+            // Debug registers aren't copied by fork, this is only for show.
+            ptrace::set_debugreg(*tid, 7, 0)?;
+            ptrace::detach(*tid)?;
+        }
+        Ok(())
+    }
+
+    pub fn follow_fork_mode(&self) -> ForkMode {
+        self.follow_fork_mode
+    }
+
+    pub fn set_follow_fork_mode(&mut self, mode: ForkMode) {
+        self.follow_fork_mode = mode;
+    }
+
+    pub fn detach_on_fork(&self) -> bool {
+        self.detach_on_fork
+    }
+
+    pub fn set_detach_on_fork(&mut self, detach: bool) {
+        self.detach_on_fork = detach;
+    }
+
+    /// Where the subordinate's stdio goes, the next time it's started.
+    pub fn io_mode(&self) -> IoMode {
+        self.io_mode
+    }
+
+    pub fn set_io_mode(&mut self, mode: IoMode) {
+        self.io_mode = mode;
+    }
+
+    /// The environment, working directory and so on to start the program
+    /// with, the next time it's started.
+    pub fn launch(&self) -> &Launch {
+        &self.launch
+    }
+
+    pub fn launch_mut(&mut self) -> &mut Launch {
+        &mut self.launch
+    }
+
+    /// The files stdin and stdout are redirected to, from the next start.
+    pub fn redirects(&self) -> &Redirects {
+        &self.redirects
+    }
+
+    pub fn set_redirects(&mut self, redirects: Redirects) {
+        self.redirects = redirects;
+    }
+
+    /// While the subordinate runs, sends what it writes to `sink` as it
+    /// arrives, and if `forward_input`, what's typed on our stdin to it.
+    /// Anything written while stopped is kept for `take_output`.
+    pub fn connect_output(&mut self, sink: Box<dyn Write + Send>, forward_input: bool) {
+        self.terminal.connect(sink, forward_input);
+    }
+
+    /// What the subordinate has written that hasn't gone to the sink given
+    /// to `connect_output`, since this was last called.
+    pub fn take_output(&mut self) -> Vec<u8> {
+        self.terminal.take_output()
+    }
+
+    /// The program and arguments the subordinate was started with.
+    pub fn cmd(&self) -> &[String] {
+        &self.cmd
+    }
+
+    pub fn pid(&self) -> i32 {
+        self.pid
+    }
+
+    /// Whether we attached to the subordinate rather than spawning it.
+    pub fn is_attached(&self) -> bool {
+        self.attached
+    }
+
+    pub fn is_detached(&self) -> bool {
+        self.detached
+    }
+
     pub fn exit_status(&self) -> Option<i32> {
-        if let Exited(_, status) = self.wait_status {
-            return Some(status);
+        match self.wait_status {
+            Exited(pid, status) if pid == self.pid => Some(status),
+            _ => None,
         }
-        None
     }
 
-    pub fn breakpoint(&mut self, addr: usize) -> Result<()> {
-        if let Some(_) = self.breakpoints.get(&addr) {
-            return Ok(());
+    /// Whether the process is still around, even if the thread we were
+    /// looking at isn't.
+    pub fn is_alive(&self) -> bool {
+        match self.wait_status {
+            Exited(pid, _) | Signaled(pid, _) => pid != self.pid,
+            _ => true,
         }
+    }
 
-        let data = self.peek(addr)?;
-        self.poke(addr, data & (usize::max_value() - 255) | 0xcc)?;
-        self.breakpoints.insert(addr, data);
+    pub fn threads(&self) -> &[Thread] {
+        &self.threads
+    }
+
+    /// The ID of the current thread, as in `Thread::id`.
+    pub fn current_thread(&self) -> usize {
+        self.threads
+            .iter()
+            .find(|thread| thread.tid == self.tid)
+            .map_or(0, |thread| thread.id)
+    }
+
+    /// Makes another thread the current one, for stepping and inspecting.
+    pub fn select_thread(&mut self, id: usize) -> Result<()> {
+        let i = match self.threads.iter().position(|thread| thread.id == id) {
+            Some(i) => i,
+            None => return Err(format!("no thread number {}", id).into()),
+        };
+        self.switch_thread(i);
+        self.stack = self.read_words(self.registers.rsp as usize, 16)?;
         Ok(())
     }
 
-    pub fn registers(&self) -> &Registers {
-        &self.registers
+    fn switch_thread(&mut self, i: usize) {
+        self.tid = self.threads[i].tid;
+        self.registers = self.threads[i].registers.clone();
+        self.frame = 0;
     }
 
-    pub fn instructions(&self, symbol: &elf::types::Symbol) -> Result<Vec<u8>> {
-        Ok(self.read_bytes(symbol.value as usize, symbol.size as usize)?)
+    /// Sets a breakpoint at the given address, returning its ID.
+    pub fn breakpoint(&mut self, location: &Location) -> Result<Vec<usize>> {
+        self.insert_breakpoints(location, BreakpointKind::Software, false)
     }
 
-    pub fn stack(&self) -> &[usize] {
-        &self.stack
+    /// Sets a breakpoint that gets deleted the first time it stops us.
+    pub fn temporary_breakpoint(&mut self, location: &Location) -> Result<Vec<usize>> {
+        self.insert_breakpoints(location, BreakpointKind::Software, true)
     }
 
-    pub fn debug_info(&self) -> &DebugInfo {
-        &self.debug_info
+    /// Sets a breakpoint using a debug register instead of an int3, which
+    /// works on memory we can't or shouldn't write to.
+    pub fn hardware_breakpoint(&mut self, location: &Location) -> Result<Vec<usize>> {
+        self.insert_breakpoints(location, BreakpointKind::Hardware, false)
     }
 
-    pub fn symbols(&self) -> &Vec<elf::types::Symbol> {
-        &self.symbols
+    /// Watches `len` bytes at `addr`, which must be 1, 2, 4 or 8 and aligned
+    /// to that size. Write watchpoints only stop when the value changes.
+    pub fn watchpoint(&mut self, addr: usize, len: usize, access: Access) -> Result<usize> {
+        if ![1, 2, 4, 8].contains(&len) {
+            return Err(format!("can't watch {} bytes, must be 1, 2, 4 or 8", len).into());
+        }
+        if !addr.is_multiple_of(len) {
+            return Err(format!("0x{:x} isn't aligned to {} bytes", addr, len).into());
+        }
+        let kind = BreakpointKind::Watchpoint(access, len);
+        self.insert_breakpoint(Location::Address(addr), Some(addr), kind, false)
     }
 
-    pub fn symbol(&self, name: &str) -> Option<&elf::types::Symbol> {
-        for symbol in &self.symbols {
-            if symbol.name == name {
-                return Some(symbol);
+    /// Stops on entry to and exit from the given syscalls, or any syscall if
+    /// none are given.
+    pub fn catch_syscalls(&mut self, syscalls: Vec<u64>) -> Result<usize> {
+        let kind = BreakpointKind::Syscall;
+        let id = self.insert_breakpoint(Location::Address(0), None, kind, false)?;
+        let bp = self.find_breakpoint(id)?;
+        bp.pending = false;
+        bp.syscalls = syscalls;
+        Ok(id)
+    }
+
+    /// Sets a breakpoint at each address `location` resolves to, a line can
+    /// have several, returning their IDs. A symbol or line that isn't in the
+    /// program gets a single pending breakpoint, in case we exec into a
+    /// program that has it.
+    fn insert_breakpoints(
+        &mut self,
+        location: &Location,
+        kind: BreakpointKind,
+        temporary: bool,
+    ) -> Result<Vec<usize>> {
+        let addrs = match self.resolve(location) {
+            Ok(addrs) => addrs,
+            Err(_) if !matches!(location, Location::Address(_)) => {
+                let id = self.insert_breakpoint(location.clone(), None, kind, temporary)?;
+                return Ok(vec![id]);
             }
+            Err(e) => return Err(e),
+        };
+
+        let mut ids = Vec::new();
+        for addr in addrs {
+            ids.push(self.insert_breakpoint(location.clone(), Some(addr), kind, temporary)?);
         }
-        None
+        Ok(ids)
     }
 
-    fn shift_symbols(&mut self, amount: u64) {
-        for symbol in &mut self.symbols {
-            if symbol.bind == elf::types::STB_WEAK {
-                continue;
+    fn insert_breakpoint(
+        &mut self,
+        spec: Location,
+        addr: Option<usize>,
+        kind: BreakpointKind,
+        temporary: bool,
+    ) -> Result<usize> {
+        let id = self.next_breakpoint_id;
+        let value = match (kind, addr) {
+            (BreakpointKind::Watchpoint(_, len), Some(addr)) if self.is_alive() => {
+                Some(self.read_value(addr, len)?)
             }
-            symbol.value += amount;
+            _ => None,
+        };
+        let (symbol, line) = match addr {
+            Some(addr) => (self.symbol_offset(addr), self.line_info(addr).cloned()),
+            None => (None, None),
+        };
+
+        self.breakpoints.push(Breakpoint {
+            id,
+            kind,
+            spec,
+            addr: addr.unwrap_or(0),
+            symbol,
+            line,
+            pending: addr.is_none(),
+            enabled: true,
+            hit_count: 0,
+            condition: None,
+            ignore_count: 0,
+            temporary,
+            value,
+            syscalls: Vec::new(),
+        });
+
+        if let Err(e) = self.sync_breakpoint(kind, addr.unwrap_or(0)) {
+            self.breakpoints.pop();
+            return Err(e);
         }
+
+        self.next_breakpoint_id += 1;
+        Ok(id)
     }
 
-    fn fetch_state(&mut self) -> Result<()> {
-        self.wait_status = wait()?;
-        if let Stopped(_, _) = self.wait_status {
-            self.registers = ptrace::getregs(self.pid)?.into();
-            self.stack = self.read_words(self.registers.rsp as usize, 16)?;
-            self.handle_breakpoint()?;
-        };
+    pub fn set_breakpoint_condition(&mut self, id: usize, condition: Option<Expr>) -> Result<()> {
+        self.find_breakpoint(id)?.condition = condition;
         Ok(())
     }
 
-    fn handle_breakpoint(&mut self) -> Result<()> {
-        let addr = (self.registers.rip - 1) as usize;
-        if let Some(data) = self.breakpoints.remove(&addr) {
-            info!("hit breakpoint: {:x}", addr);
-            self.registers.rip = addr as u64;
-            self.poke(self.registers.rip as usize, data)?;
-            ptrace::setregs(self.pid, &self.registers.clone().into())?;
+    /// Lets the breakpoint be hit `count` more times before it stops us.
+    pub fn ignore_breakpoint(&mut self, id: usize, count: usize) -> Result<()> {
+        self.find_breakpoint(id)?.ignore_count = count;
+        Ok(())
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    pub fn delete_breakpoint(&mut self, id: usize) -> Result<()> {
+        let bp = self.find_breakpoint(id)?;
+        let (kind, addr) = (bp.kind, bp.addr);
+        self.breakpoints.retain(|bp| bp.id != id);
+        self.sync_breakpoint(kind, addr)
+    }
+
+    pub fn enable_breakpoint(&mut self, id: usize) -> Result<()> {
+        self.set_breakpoint_enabled(id, true)
+    }
+
+    pub fn disable_breakpoint(&mut self, id: usize) -> Result<()> {
+        self.set_breakpoint_enabled(id, false)
+    }
+
+    fn set_breakpoint_enabled(&mut self, id: usize, enabled: bool) -> Result<()> {
+        let bp = self.find_breakpoint(id)?;
+        let (kind, addr, was_enabled) = (bp.kind, bp.addr, bp.enabled);
+        bp.enabled = enabled;
+
+        if let Err(e) = self.sync_breakpoint(kind, addr) {
+            self.find_breakpoint(id)?.enabled = was_enabled;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    fn find_breakpoint(&mut self, id: usize) -> Result<&mut Breakpoint> {
+        match self.breakpoints.iter_mut().find(|bp| bp.id == id) {
+            Some(bp) => Ok(bp),
+            None => Err(format!("no breakpoint number {}", id).into()),
+        }
+    }
+
+    /// Brings the subordinate in line with the breakpoint table after a
+    /// breakpoint of the given kind at `addr` changed.
+    fn sync_breakpoint(&mut self, kind: BreakpointKind, addr: usize) -> Result<()> {
+        // Everything gets put in place when the program is next started.
+        if !self.is_alive() {
+            return Ok(());
+        }
+        match kind {
+            // Whether we're catching syscalls is checked whenever we resume.
+            BreakpointKind::Syscall => Ok(()),
+            _ if kind.uses_debug_register() => self.sync_debug_registers(),
+            _ => self.sync_breakpoint_site(addr),
+        }
+    }
+
+    /// Makes sure there's an int3 at `addr` if, and only if, there's an
+    /// enabled breakpoint there. Several breakpoints can share an address.
+    fn sync_breakpoint_site(&mut self, addr: usize) -> Result<()> {
+        let wanted = self.internal_breakpoints.iter().any(|t| t.0 == addr)
+            || self.breakpoints.iter().any(|bp| {
+                bp.addr == addr && bp.enabled && !bp.pending && bp.kind == BreakpointKind::Software
+            });
+        match (wanted, self.inserted.get(&addr).copied()) {
+            (true, None) => {
+                let orig = self.write_byte(addr, 0xcc)?;
+                self.inserted.insert(addr, orig);
+            }
+            (false, Some(orig)) => {
+                self.write_byte(addr, orig)?;
+                self.inserted.remove(&addr);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Hands out DR0-DR3 to the enabled hardware breakpoints and watchpoints
+    /// and writes them, along with the DR7 control bits, to the subordinate.
+    fn sync_debug_registers(&mut self) -> Result<()> {
+        let wanted: Vec<&Breakpoint> = self
+            .breakpoints
+            .iter()
+            .filter(|bp| bp.enabled && !bp.pending && bp.kind.uses_debug_register())
+            .collect();
+        if wanted.len() > self.debug_registers.len() {
+            return Err("all 4 debug registers are in use".into());
+        }
+
+        let mut slots = [None; 4];
+        for (i, bp) in wanted.iter().enumerate() {
+            slots[i] = Some(bp.id);
+        }
+        self.debug_registers = slots;
+
+        // Each thread has its own debug registers. Ones that haven't
+        // started yet get theirs when they do.
+        for thread in self.threads.iter().filter(|thread| thread.started) {
+            self.write_debug_registers(thread.tid)?;
+        }
+        Ok(())
+    }
+
+    fn write_debug_registers(&self, tid: i32) -> Result<()> {
+        let mut dr7 = 0;
+        ptrace::set_debugreg(tid, 7, 0)?;
+        for (i, slot) in self.debug_registers.iter().enumerate() {
+            let bp = match slot.and_then(|id| self.breakpoints.iter().find(|bp| bp.id == id)) {
+                Some(bp) => bp,
+                None => continue,
+            };
+            ptrace::set_debugreg(tid, i, bp.addr)?;
+            dr7 |= 1 << (i * 2) | bp.kind.dr7_bits() << (i * 4);
+        }
+        ptrace::set_debugreg(tid, 7, dr7)
+    }
+
+    /// Why the subordinate last stopped.
+    pub fn stop_reason(&self) -> StopReason {
+        match self.wait_status {
+            Exited(pid, status) if pid == self.pid => return StopReason::Exited(status),
+            Signaled(pid, signal) if pid == self.pid => return StopReason::Killed(signal),
+            _ => {}
+        }
+
+        if !self.watchpoints_hit.is_empty() {
+            return StopReason::Watchpoint(self.watchpoints_hit.clone());
+        }
+        let hit = self.breakpoints_hit.clone().unwrap_or_default();
+        if let Some(ref stop) = self.syscall_stop {
+            return StopReason::Syscall(hit, stop.clone());
+        }
+        if let Some(ref stop) = self.signal_stop {
+            return StopReason::Signal(stop.clone());
+        }
+        // Our own breakpoints for stepping don't count.
+        if !hit.is_empty() {
+            return StopReason::Breakpoint(hit);
+        }
+        StopReason::Step
+    }
+
+    /// The syscall the current thread is entering or leaving, if that's why
+    /// it stopped.
+    pub fn syscall_stop(&self) -> Option<&SyscallStop> {
+        self.syscall_stop.as_ref()
+    }
+
+    /// Signals that should be printed but didn't stop us, since this was
+    /// last called.
+    pub fn take_noticed_signals(&mut self) -> Vec<SignalStop> {
+        std::mem::take(&mut self.noticed_signals)
+    }
+
+    pub fn signal_handling(&self, signal: i32) -> Result<SignalHandling> {
+        match self.signals.get(signal as usize) {
+            Some(handling) if signal > 0 => Ok(*handling),
+            _ => Err(format!("no signal number {}", signal).into()),
         }
+    }
 
+    pub fn set_signal_handling(&mut self, signal: i32, handling: SignalHandling) -> Result<()> {
+        match self.signals.get_mut(signal as usize) {
+            Some(slot) if signal > 0 => *slot = handling,
+            _ => return Err(format!("no signal number {}", signal).into()),
+        }
         Ok(())
     }
+
+    pub fn registers(&self) -> &Registers {
+        &self.registers
+    }
+
+    /// Reads one of the SSE registers, xmm0 to xmm15.
+    pub fn xmm(&self, n: usize) -> Result<u128> {
+        let fpregs = ptrace::getfpregs(self.tid)?;
+        let mut value = 0;
+        for (i, word) in fpregs.xmm_space[n * 4..n * 4 + 4].iter().enumerate() {
+            value |= (*word as u128) << (i * 32);
+        }
+        Ok(value)
+    }
+
+    pub fn instructions(&self, symbol: &elf::types::Symbol) -> Result<Vec<u8>> {
+        Ok(self.read_bytes(symbol.value as usize, symbol.size as usize)?)
+    }
+
+    /// The current thread's stack, innermost frame first.
+    pub fn backtrace(&self) -> Result<Vec<Frame>> {
+        self.check_alive()?;
+        unwind::backtrace(self, &mut self.modules.borrow_mut(), usize::MAX)
+    }
+
+    /// The frame that commands looking at registers and code use.
+    pub fn frame(&self) -> Result<Frame> {
+        self.check_alive()?;
+        let mut frames = unwind::backtrace(self, &mut self.modules.borrow_mut(), self.frame + 1)?;
+        match frames.pop() {
+            Some(frame) if frame.level == self.frame => Ok(frame),
+            _ => Err(format!("no frame at level {}", self.frame).into()),
+        }
+    }
+
+    pub fn selected_frame(&self) -> usize {
+        self.frame
+    }
+
+    /// Selects a frame by level, 0 being the innermost. Goes back to 0
+    /// whenever the subordinate stops or we switch threads.
+    pub fn select_frame(&mut self, level: usize) -> Result<Frame> {
+        let previous = self.frame;
+        self.frame = level;
+        let frame = self.frame();
+        if frame.is_err() {
+            self.frame = previous;
+        }
+        frame
+    }
+
+    /// The arguments of the selected frame's function, with their values.
+    pub fn args(&self) -> Result<Vec<(Variable, Result<Vec<u8>>)>> {
+        self.frame_variables(true)
+    }
+
+    /// The local variables in scope in the selected frame, innermost block
+    /// first, with their values.
+    pub fn locals(&self) -> Result<Vec<(Variable, Result<Vec<u8>>)>> {
+        self.frame_variables(false)
+    }
+
+    fn frame_variables(&self, args: bool) -> Result<Vec<(Variable, Result<Vec<u8>>)>> {
+        let frame = self.frame()?;
+        let bias = self.load_bias as usize;
+        // Above the innermost frame, the pc is where the call returns to,
+        // which can be the start of another block or even function.
+        let pc = if frame.level == 0 {
+            frame.pc
+        } else {
+            frame.pc - 1
+        };
+        let pc = pc.wrapping_sub(bias);
+        let function = match self.debug_info.function_for_pc(pc) {
+            Some(function) => function,
+            None => return Err(format!("no debug info for 0x{:x}", frame.pc).into()),
+        };
+
+        let context = Context {
+            subordinate: self,
+            registers: &frame.registers,
+            innermost: frame.level == 0,
+            cfa: Some(frame.cfa),
+            pc,
+            bias,
+            frame_base: function.frame_base.as_ref(),
+            tls_size: self.tls_size,
+        };
+        let variables = if args {
+            function.parameters.iter().collect()
+        } else {
+            function.locals(pc)
+        };
+        Ok(variables
+            .into_iter()
+            .map(|variable| {
+                let size = variable.ty.as_ref().map_or(8, |ty| ty.size);
+                (variable.clone(), context.read(&variable.location, size))
+            })
+            .collect())
+    }
+
+    pub fn stack(&self) -> &[usize] {
+        &self.stack
+    }
+
+    pub fn debug_info(&self) -> &DebugInfo {
+        &self.debug_info
+    }
+
+    /// Line information for an address in the running program, if it's the
+    /// first address of a row in the line table.
+    pub fn line_info(&self, addr: usize) -> Option<&LineInfo> {
+        self.debug_info.line_info(addr - self.load_bias as usize)
+    }
+
+    /// Line information for any address in the running program.
+    pub fn line_for_pc(&self, addr: usize) -> Option<&LineInfo> {
+        self.debug_info.line_for_pc(addr - self.load_bias as usize)
+    }
+
+    fn at_line_start(&self, addr: usize) -> bool {
+        self.line_info(addr).is_some_and(|line| line.is_stmt)
+    }
+
+    pub fn symbols(&self) -> &Vec<elf::types::Symbol> {
+        &self.symbols
+    }
+
+    pub fn symbol(&self, name: &str) -> Option<&elf::types::Symbol> {
+        for symbol in &self.symbols {
+            if symbol.name == name {
+                return Some(symbol);
+            }
+        }
+        None
+    }
+
+    /// Finds every address a location refers to in the running program.
+    pub fn resolve(&self, location: &Location) -> Result<Vec<usize>> {
+        match location {
+            Location::Address(addr) => Ok(vec![*addr]),
+            Location::Symbol(name, offset) => match self.symbol(name) {
+                Some(symbol) => Ok(vec![symbol.value as usize + offset]),
+                None => Err(format!("unknown symbol `{}`", name).into()),
+            },
+            Location::Line(file, line) => {
+                let bias = self.load_bias as usize;
+                let addrs = self.debug_info.addrs_for_line(file, *line);
+                if addrs.is_empty() {
+                    return Err(format!("no code for line {} in `{}`", line, file).into());
+                }
+                Ok(addrs.iter().map(|addr| addr + bias).collect())
+            }
+        }
+    }
+
+    /// Describes an address as an offset into a symbol, e.g. `main+4`.
+    fn symbol_offset(&self, addr: usize) -> Option<String> {
+        self.symbol_for_addr(addr)
+            .map(|symbol| format!("{}+{}", symbol.name, addr - symbol.value as usize))
+    }
+
+    /// Finds the symbol whose extent covers `addr`.
+    pub fn symbol_for_addr(&self, addr: usize) -> Option<&elf::types::Symbol> {
+        self.symbols.iter().find(|symbol| {
+            let start = symbol.value as usize;
+            symbol.size > 0 && start <= addr && addr < start + symbol.size as usize
+        })
+    }
+
+    fn shift_symbols(&mut self, amount: u64) {
+        for symbol in &mut self.symbols {
+            if symbol.bind == elf::types::STB_WEAK {
+                continue;
+            }
+            symbol.value += amount;
+        }
+    }
+
+    /// Like `nexti`, but returns whether the step finished normally rather
+    /// than something else, like a breakpoint or signal, stopping us first.
+    fn step_over_calls(&mut self) -> Result<bool> {
+        let instruction = self.current_instruction()?;
+        match instruction.flow_control() {
+            FlowControl::Call | FlowControl::IndirectCall => {
+                let rsp = self.registers.rsp;
+                self.run_to(vec![(instruction.next_ip() as usize, rsp)])
+            }
+            _ => {
+                self.stepi()?;
+                Ok(self.stepped_cleanly())
+            }
+        }
+    }
+
+    /// Executes one instruction, following calls only into functions that
+    /// we have line info for. Calls to anything else get run to completion.
+    fn step_into_calls(&mut self) -> Result<bool> {
+        let instruction = self.current_instruction()?;
+        let rsp = self.registers.rsp;
+
+        self.stepi()?;
+        if !self.stepped_cleanly() {
+            return Ok(false);
+        }
+
+        match instruction.flow_control() {
+            FlowControl::Call | FlowControl::IndirectCall
+                if self.line_for_pc(self.registers.rip as usize).is_none() =>
+            {
+                self.run_to(vec![(instruction.next_ip() as usize, rsp)])
+            }
+            _ => Ok(true),
+        }
+    }
+
+    fn current_instruction(&self) -> Result<Instruction> {
+        let rip = self.registers.rip;
+        let bytes = self.read_bytes(rip as usize, 16)?;
+        match Disassembler::new().decode(rip, &bytes).first() {
+            Some(instruction) => Ok(*instruction),
+            None => Err(format!("couldn't decode instruction at 0x{:x}", rip).into()),
+        }
+    }
+
+    /// Whether the last single step finished without anything else, like a
+    /// signal, a watchpoint or a breakpoint, getting in the way.
+    fn stepped_cleanly(&self) -> bool {
+        self.stopped_by(SIGTRAP)
+            && self.watchpoints_hit.is_empty()
+            && self.breakpoints_hit.as_ref().is_none_or(Vec::is_empty)
+    }
+
+    /// Works out where the current function will return to, and what the
+    /// stack pointer will be once it has, by unwinding to its caller.
+    fn return_address(&self) -> Result<(usize, u64)> {
+        let frames = unwind::backtrace(self, &mut self.modules.borrow_mut(), 2)?;
+        match &frames[..] {
+            [frame, caller] => Ok((caller.pc, frame.cfa as u64)),
+            _ => Err("the current function has no caller".into()),
+        }
+    }
+
+    /// Continues until we reach one of the given addresses with the stack
+    /// pointer at or above the given value, returning whether we made it or
+    /// were stopped by something else first.
+    fn run_to(&mut self, targets: Vec<(usize, u64)>) -> Result<bool> {
+        self.internal_breakpoints = targets;
+        let addrs: Vec<usize> = self.internal_breakpoints.iter().map(|t| t.0).collect();
+        for addr in &addrs {
+            self.sync_breakpoint_site(*addr)?;
+        }
+
+        let result = self.cont();
+        let reached = self.at_internal_breakpoint();
+
+        self.internal_breakpoints.clear();
+        if self.is_alive() {
+            for addr in &addrs {
+                self.sync_breakpoint_site(*addr)?;
+            }
+        }
+
+        result?;
+        Ok(reached)
+    }
+
+    fn at_internal_breakpoint(&self) -> bool {
+        let (rip, rsp) = (self.registers.rip as usize, self.registers.rsp);
+        self.breakpoints_hit.is_some()
+            && self.syscall_stop.is_none()
+            && self
+                .internal_breakpoints
+                .iter()
+                .any(|(addr, min_rsp)| *addr == rip && rsp >= *min_rsp)
+    }
+
+    /// Reads a little endian value of up to 8 bytes.
+    fn read_value(&self, addr: usize, len: usize) -> Result<u64> {
+        let mut buf = [0u8; 8];
+        buf[..len].copy_from_slice(&self.read_bytes(addr, len)?);
+        Ok(u64::from_le_bytes(buf))
+    }
+
+    /// Finds the address of the instruction that ends at `addr`, by decoding
+    /// forwards from the start of the enclosing function.
+    fn instruction_before(&self, addr: usize) -> Option<usize> {
+        let symbol = self.symbol_for_addr(addr - 1)?;
+        let bytes = self.instructions(symbol).ok()?;
+        Disassembler::new()
+            .decode(symbol.value, &bytes)
+            .iter()
+            .find(|instruction| instruction.next_ip() as usize == addr)
+            .map(|instruction| instruction.ip() as usize)
+    }
+
+    /// Writes a single byte to the subordinate's memory, returning the byte
+    /// that was there before.
+    fn write_byte(&self, addr: usize, byte: u8) -> Result<u8> {
+        write_byte(self.tid, addr, byte)
+    }
+
+    /// Puts all of our int3s into the memory of the process `tid` belongs
+    /// to, or takes them out again.
+    fn write_breakpoint_sites(&self, tid: i32, insert: bool) -> Result<()> {
+        for (addr, orig) in &self.inserted {
+            write_byte(tid, *addr, if insert { 0xcc } else { *orig })?;
+        }
+        Ok(())
+    }
+
+    fn stopped_by(&self, signal: i32) -> bool {
+        match self.wait_status {
+            Stopped(_, sig) => sig == signal,
+            _ => false,
+        }
+    }
+
+    /// If we're currently sat on a breakpoint, put the original instruction
+    /// back, execute it and then reinsert the breakpoint so that it fires
+    /// again next time around. Returns whether a step was taken, in which case
+    /// `wait_status` reflects the result of that step.
+    fn step_over_breakpoint(&mut self) -> Result<bool> {
+        let addr = self.registers.rip as usize;
+        let orig = match self.inserted.get(&addr) {
+            Some(orig) => *orig,
+            None => return Ok(false),
+        };
+
+        self.write_byte(addr, orig)?;
+        self.step_thread()?;
+        self.wait_event()?;
+        if self.is_alive() {
+            self.write_byte(addr, 0xcc)?;
+        }
+
+        Ok(true)
+    }
+
+    /// Does the same as `step_over_breakpoint` for every thread but the
+    /// current one, as they'd otherwise hit the breakpoint they're sat on
+    /// again as soon as they're resumed. Happens after switching threads.
+    fn step_threads_over_breakpoints(&mut self) -> Result<()> {
+        let mut exited = Vec::new();
+        for i in 0..self.threads.len() {
+            let thread = &self.threads[i];
+            let (tid, addr) = (thread.tid, thread.registers.rip as usize);
+            let orig = match self.inserted.get(&addr) {
+                Some(orig) if tid != self.tid && thread.pending.is_none() => *orig,
+                _ => continue,
+            };
+
+            self.write_byte(addr, orig)?;
+            ptrace::singlestep(tid, 0)?;
+            self.threads[i].in_syscall = None;
+            let status = waitpid(tid, __WALL)?;
+            self.write_byte(addr, 0xcc)?;
+
+            match status {
+                Stopped(_, SIGTRAP) => self.threads[i].registers = ptrace::getregs(tid)?.into(),
+                Exited(_, _) | Signaled(_, _) => exited.push(tid),
+                // Anything else can wait until it's this thread's turn.
+                other => self.threads[i].pending = Some(other),
+            }
+        }
+
+        self.threads.retain(|thread| !exited.contains(&thread.tid));
+        Ok(())
+    }
+
+    /// Single steps the current thread, leaving the others stopped.
+    fn step_thread(&mut self) -> Result<()> {
+        self.resume(self.tid, true)
+    }
+
+    /// Resumes every thread, unless one of them has a stop we haven't
+    /// reported yet, in which case that becomes the current stop instead.
+    /// Returns whether anything was resumed.
+    fn resume_all(&mut self) -> Result<bool> {
+        while let Some(i) = self
+            .threads
+            .iter()
+            .position(|thread| thread.pending.is_some())
+        {
+            match self.threads[i].pending.take() {
+                Some(PtraceEvent(tid, event)) => {
+                    self.handle_ptrace_event(tid, event)?;
+                }
+                Some(status) => {
+                    self.switch_thread(i);
+                    self.wait_status = status;
+                    return Ok(false);
+                }
+                None => {}
+            }
+        }
+
+        let stopped: Vec<i32> = self
+            .threads
+            .iter()
+            .filter(|thread| !thread.running)
+            .map(|thread| thread.tid)
+            .collect();
+        for tid in stopped {
+            self.resume(tid, false)?;
+        }
+        self.all_running = true;
+        Ok(true)
+    }
+
+    /// Waits for one of the running threads to stop for a reason the user
+    /// might care about, then stops the rest. Thread creation and exit is
+    /// dealt with along the way. The thread that stopped becomes current.
+    fn wait_event(&mut self) -> Result<()> {
+        // Ctrl-C only reaches the subordinate by itself if it's in our
+        // process group, otherwise we pass it on.
+        let target = match getpgid(self.pid) {
+            Ok(pgid) if pgid != getpgrp() => self.pid,
+            _ => 0,
+        };
+        set_interrupt_target(target);
+        self.terminal.set_live(true);
+        let status = self.wait_stop();
+        self.terminal.set_live(false);
+        set_interrupt_target(0);
+        let status = status?;
+
+        self.all_running = false;
+        self.stop_threads()?;
+
+        match status {
+            Exited(pid, _) | Signaled(pid, _) if pid == self.pid => {
+                if let Some(parent) = self.vfork_parent.take() {
+                    self.release(&parent)?;
+                }
+            }
+            _ => {}
+        }
+
+        // If the thread that stopped has gone, fall back on whichever is left.
+        let i = self
+            .threads
+            .iter()
+            .position(|thread| thread.tid == status.pid())
+            .or_else(|| {
+                self.threads
+                    .iter()
+                    .position(|thread| thread.tid == self.tid)
+            })
+            .unwrap_or(0);
+        if i < self.threads.len() {
+            self.switch_thread(i);
+        }
+        self.wait_status = status;
+        Ok(())
+    }
+
+    /// The waiting part of `wait_event`, returning the first interesting
+    /// status.
+    fn wait_stop(&mut self) -> Result<WaitStatus> {
+        let status = loop {
+            let status = waitpid(-1, __WALL)?;
+            match status {
+                PtraceEvent(tid, event) => {
+                    if let Some(thread) = self.threads.iter_mut().find(|t| t.tid == tid) {
+                        thread.running = false;
+                    }
+                    let tid = self.handle_ptrace_event(tid, event)?;
+                    self.resume_thread(tid)?;
+                }
+                Stopped(tid, SIGSTOP) if self.expecting_stop(tid) => {
+                    self.quiet_stop(tid)?;
+                    if self.all_running || tid == self.tid {
+                        self.resume_thread(tid)?;
+                    }
+                }
+                Exited(tid, _) | Signaled(tid, _) if tid != self.pid => {
+                    self.threads.retain(|thread| thread.tid != tid);
+                    if !self.threads.iter().any(|thread| thread.running) {
+                        break status;
+                    }
+                }
+                _ => {
+                    let tid = status.pid();
+                    if let Some(thread) = self.threads.iter_mut().find(|t| t.tid == tid) {
+                        thread.running = false;
+                    }
+                    break status;
+                }
+            }
+        };
+        Ok(status)
+    }
+
+    /// Stops every thread that's still running. Anything they stop for other
+    /// than our SIGSTOP is held on to, to be reported before resuming.
+    fn stop_threads(&mut self) -> Result<()> {
+        let pid = self.pid;
+        let mut stopped = Vec::new();
+        for thread in self.threads.iter_mut().filter(|t| t.running) {
+            // This fails if the thread is on its way out, in which case
+            // we'll hear about it exiting instead.
+            if !thread.stopping && tgkill(pid, thread.tid, SIGSTOP).is_ok() {
+                thread.stopping = true;
+            }
+            stopped.push(thread.tid);
+        }
+
+        while self.threads.iter().any(|thread| thread.running) {
+            let status = waitpid(-1, __WALL)?;
+            match status {
+                Stopped(tid, SIGSTOP) if self.expecting_stop(tid) => self.quiet_stop(tid)?,
+                PtraceEvent(tid, PTRACE_EVENT_CLONE) => {
+                    let new_tid = ptrace::geteventmsg(tid)? as i32;
+                    self.add_thread(new_tid);
+                    stopped.push(new_tid);
+                    if let Some(thread) = self.threads.iter_mut().find(|t| t.tid == tid) {
+                        thread.running = false;
+                    }
+                }
+                Exited(tid, _) | Signaled(tid, _) => {
+                    self.threads.retain(|thread| thread.tid != tid);
+                }
+                _ => {
+                    let tid = status.pid();
+                    if let Some(thread) = self.threads.iter_mut().find(|t| t.tid == tid) {
+                        thread.running = false;
+                        thread.pending = Some(status);
+                    }
+                }
+            }
+        }
+
+        for thread in self.threads.iter_mut() {
+            if stopped.contains(&thread.tid) {
+                thread.registers = ptrace::getregs(thread.tid)?.into();
+            }
+        }
+        Ok(())
+    }
+
+    /// Deals with one of the PTRACE_EVENT_* stops, leaving the thread that
+    /// reported it stopped. Returns the thread to resume to carry on, which
+    /// can change when following a fork or exec.
+    fn handle_ptrace_event(&mut self, tid: i32, event: i32) -> Result<i32> {
+        match event {
+            PTRACE_EVENT_CLONE => {
+                let new_tid = ptrace::geteventmsg(tid)? as i32;
+                self.add_thread(new_tid);
+                Ok(tid)
+            }
+            PTRACE_EVENT_FORK | PTRACE_EVENT_VFORK => {
+                self.handle_fork(tid, event == PTRACE_EVENT_VFORK)
+            }
+            PTRACE_EVENT_VFORK_DONE => {
+                // The child has let go of the memory it shared with us, so
+                // the breakpoints we took out for it can go back in.
+                self.write_breakpoint_sites(tid, true)?;
+                Ok(tid)
+            }
+            PTRACE_EVENT_EXEC => {
+                self.handle_exec(tid)?;
+                Ok(self.tid)
+            }
+            _ => Ok(tid),
+        }
+    }
+
+    fn handle_fork(&mut self, tid: i32, vfork: bool) -> Result<i32> {
+        let child = ptrace::geteventmsg(tid)? as i32;
+        // The child starts off traced by us, with a SIGSTOP on its way.
+        waitpid(child, __WALL)?;
+        info!("process {} forked child {}", self.pid, child);
+
+        if self.follow_fork_mode == ForkMode::Parent {
+            if self.detach_on_fork {
+                // After a vfork this takes the breakpoints out of our memory
+                // too, they go back in when the child is done with it.
+                self.release(&[child])?;
+            } else {
+                self.held.push(vec![child]);
+            }
+            return Ok(tid);
+        }
+
+        self.stop_threads()?;
+        let parent: Vec<i32> = self.threads.iter().map(|thread| thread.tid).collect();
+        if !self.detach_on_fork {
+            self.held.push(parent);
+        } else if vfork {
+            // Taking the breakpoints out of the parent would take them out
+            // of the child, so wait until the child has its own memory.
+            self.vfork_parent = Some(parent);
+        } else {
+            self.release(&parent)?;
+        }
+
+        info!("following child {}", child);
+        let mut thread = Thread::new(self.next_thread_id, child);
+        thread.running = false;
+        self.next_thread_id += 1;
+        self.pid = child;
+        self.tid = child;
+        self.threads = vec![thread];
+        self.start_thread(0)?;
+        Ok(child)
+    }
+
+    /// Loads the new program after an exec and moves breakpoints over to it.
+    fn handle_exec(&mut self, tid: i32) -> Result<()> {
+        // Only the thread that called exec survives it, and it takes over
+        // the process ID if it didn't have it already.
+        let former_tid = ptrace::geteventmsg(tid)? as i32;
+        self.threads.retain(|thread| thread.tid == former_tid);
+        match self.threads.first_mut() {
+            Some(thread) => thread.tid = self.pid,
+            None => {
+                let mut thread = Thread::new(self.next_thread_id, self.pid);
+                thread.started = true;
+                self.threads.push(thread);
+                self.next_thread_id += 1;
+            }
+        }
+        self.threads[0].running = false;
+        self.tid = self.pid;
+
+        // The parent we held on to still has breakpoints from the old
+        // program, which we're about to forget about.
+        if let Some(parent) = self.vfork_parent.take() {
+            self.release(&parent)?;
+        }
+
+        let exe = std::fs::read_link(format!("/proc/{}/exe", self.pid))?;
+        info!(
+            "process {} is executing new program: {}",
+            self.pid,
+            exe.display()
+        );
+        let (entry, debug_info, symbols, tls_size) = Subordinate::load(&exe)?;
+        self.debug_info = debug_info;
+        self.symbols = symbols;
+        self.tls_size = tls_size;
+        self.relocate(entry, auxv::read_proc(self.pid)?);
+
+        // None of our int3s or debug registers survive an exec.
+        self.inserted.clear();
+        self.internal_breakpoints.clear();
+        self.rebind_breakpoints(true)
+    }
+
+    /// Works out where each breakpoint is in the program that's now loaded
+    /// from what the user originally asked for. Any that can't be found are
+    /// left pending, as are watchpoints after an exec, whose memory has gone.
+    fn rebind_breakpoints(&mut self, exec: bool) -> Result<()> {
+        for i in 0..self.breakpoints.len() {
+            let spec = self.breakpoints[i].spec.clone();
+            // A location with several addresses made a breakpoint for each.
+            let nth = self.breakpoints[..i]
+                .iter()
+                .filter(|bp| bp.spec == spec)
+                .count();
+            let addr = match self.breakpoints[i].kind {
+                BreakpointKind::Syscall => continue,
+                BreakpointKind::Watchpoint(_, _) if exec => None,
+                _ => self
+                    .resolve(&spec)
+                    .ok()
+                    .and_then(|addrs| addrs.get(nth).copied()),
+            };
+
+            let (symbol, line) = match addr {
+                Some(addr) => (self.symbol_offset(addr), self.line_info(addr).cloned()),
+                None => (None, None),
+            };
+            let value = match (self.breakpoints[i].kind, addr) {
+                (BreakpointKind::Watchpoint(_, len), Some(addr)) => {
+                    Some(self.read_value(addr, len)?)
+                }
+                _ => None,
+            };
+            let bp = &mut self.breakpoints[i];
+            bp.value = value;
+            bp.pending = addr.is_none();
+            bp.addr = addr.unwrap_or(0);
+            bp.symbol = symbol;
+            bp.line = line;
+        }
+
+        let addrs: Vec<usize> = self.breakpoints.iter().map(|bp| bp.addr).collect();
+        for addr in addrs {
+            self.sync_breakpoint_site(addr)?;
+        }
+        self.sync_debug_registers()
+    }
+
+    /// Attaches to every thread of a process we've just attached to, other
+    /// than the main one, and waits for them to stop.
+    fn attach_threads(&mut self) -> Result<()> {
+        for entry in std::fs::read_dir(format!("/proc/{}/task", self.pid))? {
+            let tid = match entry?.file_name().to_str().and_then(|s| s.parse().ok()) {
+                Some(tid) if tid != self.pid => tid,
+                _ => continue,
+            };
+            ptrace::attach(tid)?;
+            self.add_thread(tid);
+        }
+        self.stop_threads()
+    }
+
+    /// Starts tracking a thread, which will be stopped by a SIGSTOP that we
+    /// don't want to report.
+    fn add_thread(&mut self, tid: i32) {
+        // The new thread can stop before we're told it was created.
+        if self.threads.iter().any(|thread| thread.tid == tid) {
+            return;
+        }
+        info!("new thread: {}", tid);
+        let mut thread = Thread::new(self.next_thread_id, tid);
+        thread.stopping = true;
+        self.threads.push(thread);
+        self.next_thread_id += 1;
+    }
+
+    /// Whether a SIGSTOP for this thread is one we were expecting.
+    fn expecting_stop(&self, tid: i32) -> bool {
+        match self.threads.iter().find(|thread| thread.tid == tid) {
+            Some(thread) => thread.stopping,
+            // A brand new thread we haven't been told about yet.
+            None => true,
+        }
+    }
+
+    /// Deals with a thread having stopped for a SIGSTOP we were expecting.
+    fn quiet_stop(&mut self, tid: i32) -> Result<()> {
+        self.add_thread(tid);
+        let i = match self.threads.iter().position(|thread| thread.tid == tid) {
+            Some(i) => i,
+            None => return Ok(()),
+        };
+        self.threads[i].stopping = false;
+        self.threads[i].running = false;
+        if !self.threads[i].started {
+            self.start_thread(i)?;
+        }
+        Ok(())
+    }
+
+    /// Sets up a thread the first time we see it stopped.
+    fn start_thread(&mut self, i: usize) -> Result<()> {
+        let tid = self.threads[i].tid;
+        ptrace::setoptions(tid, PTRACE_OPTIONS)?;
+        self.write_debug_registers(tid)?;
+        self.threads[i].started = true;
+        Ok(())
+    }
+
+    /// Resumes a thread the same way it was resumed before it stopped.
+    fn resume_thread(&mut self, tid: i32) -> Result<()> {
+        self.resume(tid, tid == self.tid && !self.all_running)
+    }
+
+    /// Single steps a thread, or lets it run until something stops it. That
+    /// includes its next syscall entry or exit if we're catching syscalls.
+    fn resume(&mut self, tid: i32, step: bool) -> Result<()> {
+        let catching = !step
+            && self
+                .breakpoints
+                .iter()
+                .any(|bp| bp.enabled && bp.kind == BreakpointKind::Syscall);
+        let signal = self
+            .threads
+            .iter_mut()
+            .find(|t| t.tid == tid)
+            .map_or(0, |thread| std::mem::replace(&mut thread.signal, 0));
+        if step {
+            ptrace::singlestep(tid, signal)?;
+        } else if catching {
+            ptrace::syscall(tid, signal)?;
+        } else {
+            ptrace::cont(tid, signal)?;
+        }
+
+        if let Some(thread) = self.threads.iter_mut().find(|t| t.tid == tid) {
+            thread.running = true;
+            // We only get told about a syscall's exit if we see its entry
+            // and carry on with PTRACE_SYSCALL.
+            if !catching {
+                thread.in_syscall = None;
+            }
+        }
+        Ok(())
+    }
+
+    fn fetch_state(&mut self) -> Result<()> {
+        self.wait_event()?;
+        self.update_state()
+    }
+
+    fn update_state(&mut self) -> Result<()> {
+        self.breakpoints_hit = None;
+        self.watchpoints_hit.clear();
+        self.syscall_stop = None;
+        self.signal_stop = None;
+        self.frame = 0;
+        if let Stopped(_, _) | Syscall(_) = self.wait_status {
+            self.registers = ptrace::getregs(self.tid)?.into();
+            self.stack = self.read_words(self.registers.rsp as usize, 16)?;
+            match self.wait_status {
+                Syscall(_) => self.handle_syscall(),
+                Stopped(_, SIGTRAP) => self.handle_breakpoint()?,
+                Stopped(_, signal) => self.handle_signal(signal)?,
+                _ => {}
+            }
+
+            let (tid, registers) = (self.tid, &self.registers);
+            if let Some(thread) = self.threads.iter_mut().find(|t| t.tid == tid) {
+                thread.registers = registers.clone();
+            }
+        };
+        Ok(())
+    }
+
+    fn handle_breakpoint(&mut self) -> Result<()> {
+        // Single steps also arrive as SIGTRAP, so look at the code to see
+        // what actually happened.
+        let code = ptrace::getsiginfo(self.tid)?.si_code;
+        let int3 = (self.registers.rip - 1) as usize;
+        match code {
+            ptrace::SI_KERNEL if self.inserted.contains_key(&int3) => {
+                self.handle_software_breakpoint()
+            }
+            // Someone else's int3, or a SIGTRAP sent with kill(2).
+            ptrace::SI_KERNEL => self.handle_signal(SIGTRAP),
+            code if code <= 0 => self.handle_signal(SIGTRAP),
+            // A watchpoint can fire on the same instruction as a single
+            // step, in which case the code says step, so check either way.
+            _ => {
+                self.handle_debug_register()?;
+                self.handle_step_onto_breakpoint();
+                Ok(())
+            }
+        }
+    }
+
+    /// A single step onto one of our int3s stops before it traps, but
+    /// still counts as getting to it.
+    fn handle_step_onto_breakpoint(&mut self) {
+        let addr = self.registers.rip as usize;
+        if !self.inserted.contains_key(&addr) {
+            return;
+        }
+        let reached = self
+            .breakpoints
+            .iter()
+            .filter(|bp| bp.addr == addr && bp.enabled && bp.kind == BreakpointKind::Software)
+            .map(|bp| bp.id);
+        self.breakpoints_hit
+            .get_or_insert_with(Vec::new)
+            .extend(reached);
+    }
+
+    fn handle_signal(&mut self, signal: i32) -> Result<()> {
+        let stop = SignalStop::from_siginfo(&ptrace::getsiginfo(self.tid)?);
+        info!("signal stop: {}", stop);
+
+        let tid = self.tid;
+        if self.signal_handling(signal)?.pass {
+            if let Some(thread) = self.threads.iter_mut().find(|t| t.tid == tid) {
+                thread.signal = signal;
+            }
+        }
+        self.signal_stop = Some(stop);
+        Ok(())
+    }
+
+    fn handle_syscall(&mut self) {
+        let tid = self.tid;
+        let regs = &self.registers;
+        let thread = match self.threads.iter_mut().find(|t| t.tid == tid) {
+            Some(thread) => thread,
+            None => return,
+        };
+
+        // Entry and exit stops look the same, so we keep track.
+        let stop = match thread.in_syscall.take() {
+            Some(mut stop) => {
+                stop.ret = Some(regs.rax as i64);
+                stop
+            }
+            None => {
+                let stop = SyscallStop {
+                    number: regs.orig_rax,
+                    args: [regs.rdi, regs.rsi, regs.rdx, regs.r10, regs.r8, regs.r9],
+                    ret: None,
+                };
+                thread.in_syscall = Some(stop.clone());
+                stop
+            }
+        };
+        info!("syscall stop: {}", stop);
+
+        self.breakpoints_hit = Some(
+            self.breakpoints
+                .iter()
+                .filter(|bp| bp.enabled && bp.kind == BreakpointKind::Syscall)
+                .filter(|bp| bp.syscalls.is_empty() || bp.syscalls.contains(&stop.number))
+                .map(|bp| bp.id)
+                .collect(),
+        );
+        self.syscall_stop = Some(stop);
+    }
+
+    fn handle_software_breakpoint(&mut self) -> Result<()> {
+        let addr = (self.registers.rip - 1) as usize;
+        info!("hit breakpoint: {:x}", addr);
+        self.registers.rip = addr as u64;
+        ptrace::setregs(self.tid, &self.registers.clone().into())?;
+
+        self.breakpoints_hit = Some(
+            self.breakpoints
+                .iter()
+                .filter(|bp| bp.addr == addr && bp.enabled && bp.kind == BreakpointKind::Software)
+                .map(|bp| bp.id)
+                .collect(),
+        );
+        Ok(())
+    }
+
+    fn handle_debug_register(&mut self) -> Result<()> {
+        if self.debug_registers.iter().all(Option::is_none) {
+            return Ok(());
+        }
+
+        // DR6 tells us which debug registers fired, the CPU never clears it.
+        let dr6 = ptrace::get_debugreg(self.tid, 6)?;
+        if dr6 & 0xf == 0 {
+            return Ok(());
+        }
+        ptrace::set_debugreg(self.tid, 6, 0)?;
+
+        let mut hit = Vec::new();
+        for slot in 0..self.debug_registers.len() {
+            if dr6 & (1 << slot) == 0 {
+                continue;
+            }
+            let id = match self.debug_registers[slot] {
+                Some(id) => id,
+                None => continue,
+            };
+
+            let bp = self.find_breakpoint(id)?;
+            let (kind, addr, old) = (bp.kind, bp.addr, bp.value.unwrap_or(0));
+            info!("hit {} {} at {:x}", kind.name(), id, addr);
+
+            if let BreakpointKind::Watchpoint(access, len) = kind {
+                let new = self.read_value(addr, len)?;
+                self.find_breakpoint(id)?.value = Some(new);
+                if !access.triggered_by(old, new) {
+                    continue;
+                }
+                self.watchpoints_hit.push(WatchpointHit {
+                    id,
+                    old,
+                    new,
+                    instruction: self.instruction_before(self.registers.rip as usize),
+                });
+            }
+
+            hit.push(id);
+        }
+
+        self.breakpoints_hit = Some(hit);
+        Ok(())
+    }
+
+    /// Decides whether the stop we've just seen is one the user cares about.
+    /// Signals can be skipped according to how they're handled, breakpoints
+    /// when none of the breakpoints that fired have a condition that holds
+    /// and no ignore count left.
+    fn should_stop(&mut self) -> Result<bool> {
+        if let Some(ref stop) = self.signal_stop {
+            let handling = self.signal_handling(stop.signal)?;
+            if !handling.stop && handling.print {
+                self.noticed_signals.push(stop.clone());
+            }
+            return Ok(handling.stop);
+        }
+        self.check_breakpoints()
+    }
+
+    /// Counts a hit for each breakpoint we've stopped at whose condition
+    /// holds, and narrows the ones we report down to those without an
+    /// ignore count left. Returns whether any of them, or one of our own
+    /// breakpoints, means stopping.
+    fn check_breakpoints(&mut self) -> Result<bool> {
+        // This can end up empty, e.g. for a write that didn't change the
+        // value under a watchpoint, in which case we carry on.
+        let hit = match self.breakpoints_hit {
+            Some(ref hit) => hit.clone(),
+            None => return Ok(true),
+        };
+
+        let stop = self.at_internal_breakpoint();
+        let mut triggered = Vec::new();
+        let mut finished = Vec::new();
+        for i in 0..self.breakpoints.len() {
+            let bp = &self.breakpoints[i];
+            if !hit.contains(&bp.id) {
+                continue;
+            }
+
+            let hit = match bp.condition {
+                Some(ref condition) => match condition.eval(self) {
+                    Ok(value) => value != 0,
+                    Err(e) => {
+                        // Better to stop and let the user see what's wrong
+                        // than silently run past it.
+                        warn!("error evaluating condition on breakpoint {}: {}", bp.id, e);
+                        true
+                    }
+                },
+                None => true,
+            };
+
+            if !hit {
+                continue;
+            }
+
+            let bp = &mut self.breakpoints[i];
+            bp.hit_count += 1;
+            if bp.ignore_count > 0 {
+                bp.ignore_count -= 1;
+                continue;
+            }
+
+            triggered.push(bp.id);
+            if bp.temporary {
+                finished.push(bp.id);
+            }
+        }
+
+        for id in finished {
+            self.delete_breakpoint(id)?;
+        }
+
+        self.watchpoints_hit
+            .retain(|hit| triggered.contains(&hit.id));
+        let stop = stop || !triggered.is_empty();
+        self.breakpoints_hit = Some(triggered);
+        Ok(stop)
+    }
+}
+
+/// Writes a single byte to a process's memory, returning the byte that was
+/// there before.
+fn write_byte(tid: i32, addr: usize, byte: u8) -> Result<u8> {
+    let data = ptrace::peek(tid, addr)?;
+    ptrace::poke(tid, addr, data & !0xff | byte as usize)?;
+    Ok(data as u8)
 }
@@ -0,0 +1,287 @@
+use crate::result::Result;
+use crate::sys::{self, dup2, openpty, pipe};
+use libc::{pollfd, POLLIN, STDERR_FILENO, STDIN_FILENO, STDOUT_FILENO};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::mem::{self, ManuallyDrop};
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Where the subordinate's stdin, stdout and stderr go.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IoMode {
+    /// A pseudo-terminal of its own, so it behaves as it would when run from
+    /// a shell, without its output getting mixed up with ours.
+    Pty,
+    /// A pipe for stdout and stderr, and /dev/null for stdin.
+    Pipe,
+    /// The same as ours.
+    Inherit,
+}
+
+/// Files to use for stdin and stdout instead, as in `run < in.txt > out.txt`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Redirects {
+    pub stdin: Option<PathBuf>,
+    pub stdout: Option<PathBuf>,
+}
+
+/// The files the subordinate's stdio should be, opened before forking so
+/// that the child has nothing left to do but put them in place.
+pub struct Stdio {
+    // Becomes the controlling terminal of a new session.
+    tty: Option<File>,
+    stdin: Option<File>,
+    stdout: Option<File>,
+    stderr: Option<File>,
+}
+
+impl Stdio {
+    /// Called in the child, between fork and exec.
+    pub fn install(&self) -> Result<()> {
+        if let Some(tty) = &self.tty {
+            sys::setsid()?;
+            sys::set_controlling_tty(tty.as_raw_fd())?;
+        }
+        let files = [
+            (&self.stdin, STDIN_FILENO),
+            (&self.stdout, STDOUT_FILENO),
+            (&self.stderr, STDERR_FILENO),
+        ];
+        for (file, fd) in files.iter() {
+            if let Some(file) = file {
+                dup2(file.as_raw_fd(), *fd)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Output {
+    // Whether the subordinate is running, while which what it writes goes
+    // straight to `sink`.
+    live: bool,
+    // Whether the reader thread has read something it has yet to pass on.
+    busy: bool,
+    // What's been written since the front end last asked, if it didn't go
+    // to `sink`.
+    held: Vec<u8>,
+    sink: Option<Box<dyn Write + Send>>,
+    // Whether to pass what's typed on our stdin to the subordinate while live.
+    forward_input: bool,
+}
+
+impl Output {
+    fn flush(&mut self) {
+        if let Some(sink) = &mut self.sink {
+            // Nothing sensible to do if our own stdout has gone.
+            let _ = sink.write_all(&self.held);
+            let _ = sink.flush();
+            self.held.clear();
+        }
+    }
+}
+
+/// Our end of the subordinate's stdio. A thread keeps reading what it
+/// writes, so that it never blocks on a full pipe while we wait for it.
+pub struct Terminal {
+    mode: IoMode,
+    // The pty master, or the read end of the pipe.
+    reader: Option<File>,
+    // The pty slave, or the write end of the pipe. Holding on to it means
+    // the reader doesn't see the end of the file between runs.
+    writer: Option<File>,
+    output: Arc<Mutex<Output>>,
+}
+
+impl Terminal {
+    pub fn new(mode: IoMode) -> Result<Self> {
+        let output = Arc::new(Mutex::new(Output {
+            live: false,
+            busy: false,
+            held: Vec::new(),
+            sink: None,
+            forward_input: false,
+        }));
+        let (reader, writer) = match mode {
+            IoMode::Pty => {
+                let (master, slave) = openpty()?;
+                // Whatever's typed is echoed by our terminal already.
+                sys::disable_echo(slave.as_raw_fd())?;
+                let input = master.try_clone()?;
+                Terminal::pump(master.try_clone()?, Some(input), output.clone());
+                (Some(master), Some(slave))
+            }
+            IoMode::Pipe => {
+                let (read, write) = pipe()?;
+                Terminal::pump(read.try_clone()?, None, output.clone());
+                (Some(read), Some(write))
+            }
+            IoMode::Inherit => (None, None),
+        };
+
+        Ok(Terminal {
+            mode,
+            reader,
+            writer,
+            output,
+        })
+    }
+
+    /// Makes a new terminal of a different kind, keeping the same sink.
+    pub fn reopen(&mut self, mode: IoMode) -> Result<()> {
+        let terminal = Terminal::new(mode)?;
+        {
+            let mut old = self.output.lock().unwrap();
+            let mut new = terminal.output.lock().unwrap();
+            new.sink = old.sink.take();
+            new.forward_input = old.forward_input;
+            new.held = mem::take(&mut old.held);
+        }
+        *self = terminal;
+        Ok(())
+    }
+
+    pub fn mode(&self) -> IoMode {
+        self.mode
+    }
+
+    /// Opens what the next process we start should have for stdio.
+    pub fn stdio(&self, redirects: &Redirects) -> Result<Stdio> {
+        let mut stdio = match (self.mode, &self.writer) {
+            (IoMode::Pty, Some(slave)) => Stdio {
+                tty: Some(slave.try_clone()?),
+                stdin: Some(slave.try_clone()?),
+                stdout: Some(slave.try_clone()?),
+                stderr: Some(slave.try_clone()?),
+            },
+            (IoMode::Pipe, Some(write)) => Stdio {
+                tty: None,
+                stdin: Some(File::open("/dev/null")?),
+                stdout: Some(write.try_clone()?),
+                stderr: Some(write.try_clone()?),
+            },
+            _ => Stdio {
+                tty: None,
+                stdin: None,
+                stdout: None,
+                stderr: None,
+            },
+        };
+
+        if let Some(path) = &redirects.stdin {
+            let file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            stdio.stdin = Some(file);
+        }
+        if let Some(path) = &redirects.stdout {
+            let file = File::create(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            stdio.stdout = Some(file);
+        }
+        Ok(stdio)
+    }
+
+    /// While the subordinate runs, sends what it writes to `sink` as it
+    /// arrives, and if `forward_input`, what's typed on our stdin to it.
+    pub fn connect(&self, sink: Box<dyn Write + Send>, forward_input: bool) {
+        let mut output = self.output.lock().unwrap();
+        output.sink = Some(sink);
+        output.forward_input = forward_input;
+    }
+
+    /// Says whether the subordinate is about to run or has just stopped.
+    /// Once stopped, everything it wrote before stopping has been passed on
+    /// or held.
+    pub fn set_live(&self, live: bool) {
+        if live {
+            let mut output = self.output.lock().unwrap();
+            output.live = true;
+            output.flush();
+        } else {
+            self.settle();
+            self.output.lock().unwrap().live = false;
+        }
+    }
+
+    /// What the subordinate has written that hasn't gone to the sink.
+    pub fn take_output(&self) -> Vec<u8> {
+        mem::take(&mut self.output.lock().unwrap().held)
+    }
+
+    /// Waits for the reader thread to catch up with what's been written.
+    fn settle(&self) {
+        let reader = match &self.reader {
+            Some(reader) => reader,
+            None => return,
+        };
+        loop {
+            let mut fds = [pollfd {
+                fd: reader.as_raw_fd(),
+                events: POLLIN,
+                revents: 0,
+            }];
+            let ready = sys::poll(&mut fds, 0).unwrap_or(0);
+            if ready == 0 && !self.output.lock().unwrap().busy {
+                return;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    /// Starts the thread copying from `reader` into `output`, and from our
+    /// stdin to `input` when forwarding it.
+    fn pump(mut reader: File, mut input: Option<File>, output: Arc<Mutex<Output>>) {
+        thread::spawn(move || {
+            // Not io::stdin(), which would buffer more than we pass on.
+            let mut stdin = ManuallyDrop::new(unsafe { File::from_raw_fd(STDIN_FILENO) });
+            let mut buf = [0; 4096];
+            loop {
+                let forward = {
+                    let output = output.lock().unwrap();
+                    output.live && output.forward_input && input.is_some()
+                };
+                let mut fds = [
+                    pollfd {
+                        fd: reader.as_raw_fd(),
+                        events: POLLIN,
+                        revents: 0,
+                    },
+                    pollfd {
+                        fd: STDIN_FILENO,
+                        events: POLLIN,
+                        revents: 0,
+                    },
+                ];
+                let nfds = if forward { 2 } else { 1 };
+                // Wake up now and then to see whether to start forwarding.
+                if sys::poll(&mut fds[..nfds], 100).is_err() {
+                    continue;
+                }
+
+                if fds[0].revents != 0 {
+                    output.lock().unwrap().busy = true;
+                    let n = reader.read(&mut buf).unwrap_or(0);
+                    let mut output = output.lock().unwrap();
+                    output.busy = false;
+                    if n == 0 {
+                        return;
+                    }
+                    output.held.extend_from_slice(&buf[..n]);
+                    if output.live {
+                        output.flush();
+                    }
+                } else if forward && fds[1].revents != 0 {
+                    let n = stdin.read(&mut buf).unwrap_or(0);
+                    match &mut input {
+                        Some(input) if n > 0 => {
+                            let _ = input.write_all(&buf[..n]);
+                        }
+                        _ => output.lock().unwrap().forward_input = false,
+                    }
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,43 @@
+use crate::debugger::{Registers, SyscallStop};
+use crate::sys::WaitStatus;
+
+/// One thread (LWP) of the subordinate. Whenever one thread stops we stop
+/// all of them, and they only ever get resumed all together, apart from the
+/// current thread being single stepped on its own.
+#[derive(Debug)]
+pub struct Thread {
+    /// Numbered from 1 in the order we saw them, for the user's benefit.
+    pub id: usize,
+    pub tid: i32,
+    pub registers: Registers,
+    // Resumed, and we haven't seen it stop since.
+    pub(super) running: bool,
+    // There's a SIGSTOP on its way to it that we don't want to report,
+    // either one we sent or the one new threads start off with.
+    pub(super) stopping: bool,
+    // Why it stopped, if it was for some reason of its own while we were
+    // stopping everything for another thread. Reported before resuming.
+    pub(super) pending: Option<WaitStatus>,
+    // Whether we've seen it stop yet, and so had a chance to set it up.
+    pub(super) started: bool,
+    // The syscall it's in the middle of, between its entry and exit stops.
+    pub(super) in_syscall: Option<SyscallStop>,
+    // A signal it stopped for, to be delivered when it's resumed.
+    pub(super) signal: i32,
+}
+
+impl Thread {
+    pub(super) fn new(id: usize, tid: i32) -> Self {
+        Thread {
+            id,
+            tid,
+            registers: Registers::default(),
+            running: true,
+            stopping: false,
+            pending: None,
+            started: false,
+            in_syscall: None,
+            signal: 0,
+        }
+    }
+}
@@ -0,0 +1,480 @@
+use crate::debugger::{
+    dwarf::{Context, Expression, LineInfo},
+    Registers, Subordinate,
+};
+use crate::result::Result;
+use gimli::{
+    BaseAddresses, CfaRule, DebugFrame, EhFrame, Encoding, EndianSlice, Format, RegisterRule,
+    RunTimeEndian, UninitializedUnwindContext, UnwindSection, UnwindTableRow, X86_64,
+};
+use object::{Object, ObjectSection, ObjectSegment, SymbolKind};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// Where `backtrace` gives up, in case the stack is corrupt in a way that
+/// has it going round in circles.
+const MAX_FRAMES: usize = 1024;
+
+/// What expressions in call frame information are read as. Unlike in
+/// .debug_info, there's no unit header to say.
+const CFI_ENCODING: Encoding = Encoding {
+    format: Format::Dwarf32,
+    version: 4,
+    address_size: 8,
+};
+
+/// One function call on the subordinate's stack.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    /// 0 for the innermost frame, counting up towards main.
+    pub level: usize,
+    pub pc: usize,
+    /// The canonical frame address, the stack pointer before the call that
+    /// made this frame.
+    pub cfa: usize,
+    /// The registers as they were in this frame. Above frame 0, only the
+    /// callee-saved ones, rip and rsp can be relied on.
+    pub registers: Registers,
+    /// Where this frame saved its caller's registers, by DWARF number.
+    pub saved: Vec<(u16, usize)>,
+    /// The function `pc` is in and how far into it, if there's a symbol.
+    pub function: Option<(String, usize)>,
+    pub line: Option<LineInfo>,
+    /// The file the code was loaded from.
+    pub module: Option<PathBuf>,
+}
+
+impl Frame {
+    /// The value of a register in this frame, or `None` if it's one the
+    /// frame's callees were free to clobber.
+    pub fn register(&self, name: &str) -> Option<u64> {
+        if self.level > 0 && !Registers::callee_saved(name) {
+            return None;
+        }
+        self.registers.get(name)
+    }
+}
+
+// A row of the unwind table, copied out of the section so that it doesn't
+// borrow from it.
+#[derive(Debug)]
+struct Row {
+    cfa: Cfa,
+    registers: Vec<(u16, Rule)>,
+    // Whether the return address is undefined, which is how _start and
+    // thread entry points say there's nothing further up the stack.
+    outermost: bool,
+}
+
+#[derive(Debug)]
+enum Cfa {
+    RegisterOffset(u16, i64),
+    Expression(Expression),
+}
+
+#[derive(Debug)]
+enum Rule {
+    Undefined,
+    SameValue,
+    Offset(i64),
+    ValOffset(i64),
+    Register(u16),
+    Expression(Expression),
+    ValExpression(Expression),
+    Architectural,
+}
+
+impl Row {
+    fn new(row: &UnwindTableRow<EndianSlice<RunTimeEndian>>) -> Self {
+        let cfa = match row.cfa() {
+            CfaRule::RegisterAndOffset { register, offset } => {
+                Cfa::RegisterOffset(register.0, *offset)
+            }
+            CfaRule::Expression(expression) => {
+                Cfa::Expression(Expression::new(*expression, CFI_ENCODING))
+            }
+        };
+        let registers = row
+            .registers()
+            .map(|(register, rule)| {
+                let rule = match rule {
+                    RegisterRule::Undefined => Rule::Undefined,
+                    RegisterRule::SameValue => Rule::SameValue,
+                    RegisterRule::Offset(n) => Rule::Offset(*n),
+                    RegisterRule::ValOffset(n) => Rule::ValOffset(*n),
+                    RegisterRule::Register(r) => Rule::Register(r.0),
+                    RegisterRule::Expression(expression) => {
+                        Rule::Expression(Expression::new(*expression, CFI_ENCODING))
+                    }
+                    RegisterRule::ValExpression(expression) => {
+                        Rule::ValExpression(Expression::new(*expression, CFI_ENCODING))
+                    }
+                    RegisterRule::Architectural => Rule::Architectural,
+                };
+                (register.0, rule)
+            })
+            .collect();
+        let outermost = row.register(X86_64::RA) == RegisterRule::Undefined;
+        Row {
+            cfa,
+            registers,
+            outermost,
+        }
+    }
+}
+
+/// What we need from an ELF file mapped into the subordinate to unwind
+/// through its code and name its functions. Addresses are as in the file.
+struct Module {
+    endian: RunTimeEndian,
+    eh_frame: Vec<u8>,
+    debug_frame: Vec<u8>,
+    bases: BaseAddresses,
+    // Functions, sorted by address, as start, size and name.
+    symbols: Vec<(u64, u64, String)>,
+    // The file offset and address of each loadable segment, to work out
+    // where the file was loaded.
+    segments: Vec<(u64, u64, u64)>,
+}
+
+impl Module {
+    fn load(path: &PathBuf) -> Result<Self> {
+        let data = std::fs::read(path)?;
+        let object =
+            object::File::parse(&data).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let endian = if object.is_little_endian() {
+            RunTimeEndian::Little
+        } else {
+            RunTimeEndian::Big
+        };
+
+        let section = |name| match object.section_by_name(name) {
+            Some(section) => {
+                let data = section.uncompressed_data().map(Cow::into_owned);
+                (section.address(), data.unwrap_or_default())
+            }
+            None => (0, Vec::new()),
+        };
+        let (eh_frame_addr, eh_frame) = section(".eh_frame");
+        let (_, debug_frame) = section(".debug_frame");
+        let mut bases = BaseAddresses::default().set_eh_frame(eh_frame_addr);
+        if let Some(text) = object.section_by_name(".text") {
+            bases = bases.set_text(text.address());
+        }
+        if let Some(got) = object.section_by_name(".got") {
+            bases = bases.set_got(got.address());
+        }
+
+        let mut symbols: Vec<(u64, u64, String)> = object
+            .symbols()
+            .chain(object.dynamic_symbols())
+            .filter(|(_, symbol)| symbol.kind() == SymbolKind::Text && symbol.size() > 0)
+            .filter_map(|(_, symbol)| {
+                let name = symbol.name()?.to_owned();
+                Some((symbol.address(), symbol.size(), name))
+            })
+            .collect();
+        symbols.sort();
+        symbols.dedup_by_key(|symbol| symbol.0);
+
+        let segments = object
+            .segments()
+            .map(|segment| {
+                let (offset, size) = segment.file_range();
+                (offset, size, segment.address())
+            })
+            .collect();
+
+        Ok(Module {
+            endian,
+            eh_frame,
+            debug_frame,
+            bases,
+            symbols,
+            segments,
+        })
+    }
+
+    /// How far the file was moved when loaded, given that the part of it at
+    /// `offset` was mapped at `start`.
+    fn bias(&self, start: u64, offset: u64) -> Option<u64> {
+        self.segments
+            .iter()
+            .find(|(file_offset, size, _)| *file_offset <= offset && offset < file_offset + size)
+            .map(|(file_offset, _, addr)| start.wrapping_sub(addr + (offset - file_offset)))
+    }
+
+    fn row(&self, addr: u64) -> Option<Row> {
+        let mut ctx = UninitializedUnwindContext::new();
+        let eh_frame = EhFrame::new(&self.eh_frame, self.endian);
+        if let Ok(row) =
+            eh_frame.unwind_info_for_address(&self.bases, &mut ctx, addr, EhFrame::cie_from_offset)
+        {
+            return Some(Row::new(&row));
+        }
+        let debug_frame = DebugFrame::new(&self.debug_frame, self.endian);
+        debug_frame
+            .unwind_info_for_address(&self.bases, &mut ctx, addr, DebugFrame::cie_from_offset)
+            .ok()
+            .map(|row| Row::new(&row))
+    }
+
+    fn function(&self, addr: u64) -> Option<(String, usize)> {
+        let i = match self.symbols.binary_search_by_key(&addr, |symbol| symbol.0) {
+            Ok(i) => i,
+            Err(0) => return None,
+            Err(i) => i - 1,
+        };
+        let (start, size, name) = &self.symbols[i];
+        if addr < start + size {
+            Some((name.clone(), (addr - start) as usize))
+        } else {
+            None
+        }
+    }
+}
+
+// A file mapped into the subordinate's address space.
+struct Mapping {
+    start: u64,
+    end: u64,
+    offset: u64,
+    executable: bool,
+    // Missing for anonymous memory, and things like [vdso].
+    path: Option<PathBuf>,
+}
+
+impl Mapping {
+    fn contains(&self, addr: u64) -> bool {
+        self.start <= addr && addr < self.end
+    }
+}
+
+fn read_mappings(pid: i32) -> Result<Vec<Mapping>> {
+    let maps = std::fs::read_to_string(format!("/proc/{}/maps", pid))?;
+    let mut mappings = Vec::new();
+    for line in maps.lines() {
+        // e.g. 7ffff7dd3000-7ffff7df5000 r-xp 00001000 08:01 1234 /lib/ld.so
+        let fields: Vec<&str> = line.splitn(6, ' ').collect();
+        if fields.len() < 5 {
+            continue;
+        }
+        let path = match fields.get(5).map(|path| path.trim_start()) {
+            Some(path) if path.starts_with('/') => Some(PathBuf::from(path)),
+            _ => None,
+        };
+        let mut range = fields[0].split('-');
+        let start = range.next().and_then(|s| u64::from_str_radix(s, 16).ok());
+        let end = range.next().and_then(|s| u64::from_str_radix(s, 16).ok());
+        let offset = u64::from_str_radix(fields[2], 16).ok();
+        if let (Some(start), Some(end), Some(offset)) = (start, end, offset) {
+            mappings.push(Mapping {
+                start,
+                end,
+                offset,
+                executable: fields[1].contains('x'),
+                path,
+            });
+        }
+    }
+    Ok(mappings)
+}
+
+/// The ELF files mapped into the subordinate, each loaded the first time
+/// we need to unwind through it.
+#[derive(Default)]
+pub struct Modules {
+    // `None` for files we couldn't make sense of, so we don't keep trying.
+    loaded: HashMap<PathBuf, Option<Module>>,
+}
+
+impl Modules {
+    /// Forgets everything, as the files may have changed since.
+    pub fn clear(&mut self) {
+        self.loaded.clear();
+    }
+
+    // Finds the module `addr` is in, along with how far it was moved.
+    fn find<'a>(
+        &'a mut self,
+        mappings: &'a [Mapping],
+        addr: u64,
+    ) -> Option<(&'a Module, u64, &'a PathBuf)> {
+        let mapping = mappings.iter().find(|m| m.contains(addr))?;
+        let path = mapping.path.as_ref()?;
+        let module = self
+            .loaded
+            .entry(path.clone())
+            .or_insert_with(|| Module::load(path).ok())
+            .as_ref()?;
+        let bias = module.bias(mapping.start, mapping.offset)?;
+        Some((module, bias, path))
+    }
+}
+
+/// Walks the current thread's stack, using the call frame information in
+/// .eh_frame or .debug_frame where there is some and following the chain
+/// of saved frame pointers where there isn't. Stops after `limit` frames.
+pub fn backtrace(
+    subordinate: &Subordinate,
+    modules: &mut Modules,
+    limit: usize,
+) -> Result<Vec<Frame>> {
+    let mappings = read_mappings(subordinate.pid())?;
+    let mut frames: Vec<Frame> = Vec::new();
+    let mut registers = subordinate.registers().clone();
+
+    while frames.len() < limit.min(MAX_FRAMES) {
+        let level = frames.len();
+        let pc = registers.rip as usize;
+        let lookup = if level == 0 { pc } else { pc - 1 } as u64;
+
+        let found = modules.find(&mappings, lookup);
+        let row = found.and_then(|(module, bias, _)| module.row(lookup.wrapping_sub(bias)));
+        // The function is the one the call is in, but the offset shown is
+        // that of the pc.
+        let function = match subordinate.symbol_for_addr(lookup as usize) {
+            Some(symbol) => Some((symbol.name.clone(), lookup as usize - symbol.value as usize)),
+            None => found.and_then(|(module, bias, _)| module.function(lookup.wrapping_sub(bias))),
+        }
+        .map(|(name, offset)| (name, offset + pc - lookup as usize));
+        let module = found.map(|(_, _, path)| path.clone());
+
+        let bias = found.map_or(0, |(_, bias, _)| bias as usize);
+        let context = Context {
+            subordinate,
+            registers: &registers,
+            innermost: level == 0,
+            cfa: None,
+            pc: lookup.wrapping_sub(bias as u64) as usize,
+            bias,
+            frame_base: None,
+            tls_size: None,
+        };
+        let unwound = match row.and_then(|row| unwind_cfi(&context, &row)) {
+            Some(unwound) => unwound,
+            None => unwind_frame_pointer(subordinate, &registers),
+        };
+        frames.push(Frame {
+            level,
+            pc,
+            cfa: unwound.cfa as usize,
+            registers,
+            saved: unwound.saved,
+            function,
+            line: subordinate.line_for_pc(lookup as usize).cloned(),
+            module,
+        });
+
+        registers = match unwound.caller {
+            Some(caller) => caller,
+            None => break,
+        };
+        // Going back up the stack has to make progress, and get us to
+        // code, or we're looking at garbage.
+        let in_code = mappings
+            .iter()
+            .any(|m| m.executable && m.contains(registers.rip));
+        if !in_code || (level > 0 && frames[level].cfa <= frames[level - 1].cfa) {
+            break;
+        }
+    }
+
+    Ok(frames)
+}
+
+// What unwinding a frame tells us about it and its caller.
+struct Unwound {
+    cfa: u64,
+    // Where the frame saved its caller's registers.
+    saved: Vec<(u16, usize)>,
+    // `None` for the outermost frame, or if the stack can't be read.
+    caller: Option<Registers>,
+}
+
+/// Works out the caller's registers from a row of the unwind table, with
+/// `context` for the frame being unwound. Returns `None` for rules we can't
+/// follow.
+fn unwind_cfi(context: &Context, row: &Row) -> Option<Unwound> {
+    let (subordinate, registers) = (context.subordinate, context.registers);
+    let cfa = match &row.cfa {
+        Cfa::RegisterOffset(register, offset) => {
+            (registers.get_dwarf(*register)? as i64 + offset) as u64
+        }
+        Cfa::Expression(expression) => context.address(expression, None).ok()?,
+    };
+    let mut unwound = Unwound {
+        cfa,
+        saved: Vec::new(),
+        caller: None,
+    };
+    if row.outermost {
+        return Some(unwound);
+    }
+
+    let mut caller = registers.clone();
+    // On x86-64, the CFA is the stack pointer from before the call.
+    caller.rsp = cfa;
+    for (register, rule) in &row.registers {
+        let value = match rule {
+            Rule::Undefined | Rule::SameValue | Rule::Architectural => continue,
+            Rule::Offset(n) => {
+                let addr = (cfa as i64 + n) as usize;
+                unwound.saved.push((*register, addr));
+                match subordinate.peek(addr) {
+                    Ok(value) => value as u64,
+                    Err(_) => return Some(unwound),
+                }
+            }
+            // Only really seen in signal trampolines, where the registers
+            // are in the signal frame.
+            Rule::Expression(expression) => {
+                let addr = context.address(expression, Some(cfa)).ok()? as usize;
+                unwound.saved.push((*register, addr));
+                match subordinate.peek(addr) {
+                    Ok(value) => value as u64,
+                    Err(_) => return Some(unwound),
+                }
+            }
+            Rule::ValOffset(n) => (cfa as i64 + n) as u64,
+            Rule::Register(other) => match registers.get_dwarf(*other) {
+                Some(value) => value,
+                None => continue,
+            },
+            Rule::ValExpression(expression) => context.address(expression, Some(cfa)).ok()?,
+        };
+        caller.set_dwarf(*register, value);
+    }
+    unwound.caller = Some(caller);
+    Some(unwound)
+}
+
+/// Works out the caller's registers assuming the frame starts with the
+/// usual push rbp; mov rbp, rsp.
+fn unwind_frame_pointer(subordinate: &Subordinate, registers: &Registers) -> Unwound {
+    let rbp = registers.rbp as usize;
+    let mut unwound = Unwound {
+        cfa: 0,
+        saved: Vec::new(),
+        caller: None,
+    };
+    if rbp == 0 {
+        return unwound;
+    }
+    unwound.cfa = rbp as u64 + 16;
+    unwound.saved = vec![(X86_64::RBP.0, rbp), (X86_64::RA.0, rbp + 8)];
+
+    let mut caller = registers.clone();
+    caller.rbp = match subordinate.peek(rbp) {
+        Ok(value) => value as u64,
+        Err(_) => return unwound,
+    };
+    caller.rip = match subordinate.peek(rbp + 8) {
+        Ok(value) => value as u64,
+        Err(_) => return unwound,
+    };
+    caller.rsp = unwound.cfa;
+    unwound.caller = Some(caller);
+    unwound
+}
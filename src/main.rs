@@ -6,16 +6,19 @@ mod debugger;
 mod error;
 mod result;
 mod sys;
-// mod tui;
+mod trace;
+mod tui;
 
 #[macro_use]
 extern crate log;
 
 use crate::cli::Cli;
-use crate::debugger::Subordinate;
+use crate::debugger::{IoMode, Subordinate};
 use crate::error::Error;
 use crate::result::Result;
-use crate::sys::{disable_aslr, strerror};
+use crate::sys::strerror;
+use crate::trace::Tracer;
+use crate::tui::Tui;
 use human_panic::setup_panic;
 use std::env::args;
 use std::process::exit;
@@ -39,8 +42,20 @@ fn main() {
 }
 
 fn app() -> Result<()> {
-    disable_aslr()?;
-    let subordinate = Subordinate::spawn(args().skip(1).collect())?;
+    let args: Vec<String> = args().skip(1).collect();
+    let subordinate = match args.as_slice() {
+        [flag, pid] if flag == "--pid" => Subordinate::attach(pid.parse()?)?,
+        [flag, cmd @ ..] if flag == "--trace-syscalls" => {
+            let subordinate = Subordinate::spawn(cmd.to_vec(), IoMode::Inherit)?;
+            let status = Tracer::new(subordinate).start()?;
+            exit(status);
+        }
+        [flag, cmd @ ..] if flag == "--tui" => {
+            let subordinate = Subordinate::spawn(cmd.to_vec(), IoMode::Pty)?;
+            return Tui::new(subordinate).start();
+        }
+        _ => Subordinate::spawn(args, IoMode::Pty)?,
+    };
     let mut cli = Cli::new(subordinate);
     cli.start()?;
     Ok(())
@@ -3,14 +3,22 @@ pub mod ptrace;
 use crate::error::Error;
 use crate::result::Result;
 use libc::{
-    __errno_location, c_int, dup2 as libcdup2, execvp as libcexecvp, fork as libcfork,
-    personality as libcpersonality, pid_t, pipe as libcpipe, strerror as libcstrerror,
-    wait as libcwait, WEXITSTATUS, WIFCONTINUED, WIFEXITED, WIFSIGNALED, WIFSTOPPED, WSTOPSIG,
-    WTERMSIG,
+    __errno_location, c_char, c_int, chdir as libcchdir, dup2 as libcdup2, execvpe as libcexecvpe,
+    fork as libcfork, getpgid as libcgetpgid, getpgrp as libcgetpgrp, grantpt, ioctl,
+    isatty as libcisatty, kill as libckill, nfds_t, personality as libcpersonality, pid_t,
+    pipe2 as libcpipe2, poll as libcpoll, pollfd, posix_openpt, ptsname_r, setsid as libcsetsid,
+    sigaction as libcsigaction, sigemptyset, strerror as libcstrerror, syscall, tcgetattr,
+    tcsetattr, termios, unlockpt, waitpid as libcwaitpid, SYS_tgkill, ECHO, O_CLOEXEC, O_NOCTTY,
+    O_RDWR, SA_RESTART, SIGINT, SIGTRAP, TCSANOW, TIOCSCTTY, WEXITSTATUS, WIFCONTINUED, WIFEXITED,
+    WIFSIGNALED, WIFSTOPPED, WSTOPSIG, WTERMSIG,
 };
-use std::ffi::CString;
-use std::fs::File;
-use std::os::unix::io::{FromRawFd, RawFd};
+use std::ffi::{CStr, CString, OsStr, OsString};
+use std::fs::{File, OpenOptions};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::path::Path;
+use std::sync::atomic::{AtomicI32, Ordering};
 
 const ADDR_NO_RANDOMIZE: u64 = 0x40000;
 
@@ -45,7 +53,8 @@ pub fn strerror(errno: c_int) -> Result<String> {
     Ok(cs.into_string()?)
 }
 
-pub fn execvp(cmd: &Vec<String>) -> Result<()> {
+/// Runs `cmd`, searching PATH for it, with `env` as its environment.
+pub fn execvpe(cmd: &Vec<String>, env: &[(OsString, OsString)]) -> Result<()> {
     if cmd.is_empty() {
         return Err("command cannot be empty".into());
     }
@@ -54,30 +63,79 @@ pub fn execvp(cmd: &Vec<String>) -> Result<()> {
     for arg in cmd {
         cstr_array.push(CString::new(arg.clone())?);
     }
-    let mut ptr_array = Vec::with_capacity(cmd.len());
+    let mut ptr_array = Vec::with_capacity(cmd.len() + 1);
     for arg in &cstr_array {
         ptr_array.push(arg.as_ptr());
     }
+    // execvpe(3) finds the end of argv and envp by a NULL.
+    ptr_array.push(std::ptr::null());
+
+    let mut env_cstr_array = Vec::with_capacity(env.len());
+    for (name, value) in env {
+        let mut var = name.as_bytes().to_vec();
+        var.push(b'=');
+        var.extend_from_slice(value.as_bytes());
+        env_cstr_array.push(CString::new(var)?);
+    }
+    let mut env_ptr_array = Vec::with_capacity(env.len() + 1);
+    for var in &env_cstr_array {
+        env_ptr_array.push(var.as_ptr());
+    }
+    env_ptr_array.push(std::ptr::null());
 
     errwrap(|| unsafe {
-        libcexecvp(*ptr_array.first().unwrap(), ptr_array.as_ptr());
+        libcexecvpe(
+            *ptr_array.first().unwrap(),
+            ptr_array.as_ptr(),
+            env_ptr_array.as_ptr(),
+        );
     })
 }
 
-#[derive(Debug)]
+pub fn chdir(path: &Path) -> Result<()> {
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    errwrap(|| unsafe { libcchdir(path.as_ptr()) })?;
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy)]
 pub enum WaitStatus {
     Stopped(pid_t, i32),
+    /// Stopped by one of the PTRACE_EVENT_* events we asked to be told about.
+    PtraceEvent(pid_t, i32),
+    /// Stopped on entry to or exit from a syscall, with PTRACE_O_TRACESYSGOOD.
+    Syscall(pid_t),
     Continued(pid_t),
     Exited(pid_t, i32),
     Signaled(pid_t, i32),
     Unknwon(pid_t, i32),
 }
 
-pub fn wait() -> Result<WaitStatus> {
+impl WaitStatus {
+    pub fn pid(&self) -> pid_t {
+        match *self {
+            WaitStatus::Stopped(pid, _)
+            | WaitStatus::PtraceEvent(pid, _)
+            | WaitStatus::Syscall(pid)
+            | WaitStatus::Continued(pid)
+            | WaitStatus::Exited(pid, _)
+            | WaitStatus::Signaled(pid, _)
+            | WaitStatus::Unknwon(pid, _) => pid,
+        }
+    }
+}
+
+/// Waits for a child, or a thread of one with __WALL, to change state. `pid`
+/// is as for waitpid(2), -1 waits for anything.
+pub fn waitpid(pid: pid_t, options: c_int) -> Result<WaitStatus> {
     let mut status = 0;
-    let pid = errwrap(|| unsafe { libcwait(&mut status) })?;
+    let pid = errwrap(|| unsafe { libcwaitpid(pid, &mut status, options) })?;
 
-    let ws = if unsafe { WIFSTOPPED(status) } {
+    let ws = if unsafe { WIFSTOPPED(status) } && status >> 16 != 0 {
+        WaitStatus::PtraceEvent(pid, status >> 16)
+    } else if unsafe { WIFSTOPPED(status) } && unsafe { WSTOPSIG(status) } == SIGTRAP | 0x80 {
+        WaitStatus::Syscall(pid)
+    } else if unsafe { WIFSTOPPED(status) } {
         let stopsig = unsafe { WSTOPSIG(status) };
         WaitStatus::Stopped(pid, stopsig)
     } else if unsafe { WIFEXITED(status) } {
@@ -95,9 +153,54 @@ pub fn wait() -> Result<WaitStatus> {
     Ok(ws)
 }
 
+pub fn kill(pid: pid_t, signal: c_int) -> Result<()> {
+    errwrap(|| unsafe { libckill(pid, signal) })?;
+    Ok(())
+}
+
+/// Sends a signal to one thread of a process.
+pub fn tgkill(pid: pid_t, tid: pid_t, signal: c_int) -> Result<()> {
+    errwrap(|| unsafe { syscall(SYS_tgkill, pid, tid, signal) })?;
+    Ok(())
+}
+
+pub fn getpgid(pid: pid_t) -> Result<pid_t> {
+    errwrap(|| unsafe { libcgetpgid(pid) })
+}
+
+pub fn getpgrp() -> pid_t {
+    unsafe { libcgetpgrp() }
+}
+
+/// Where `on_sigint` sends a SIGINT on to, 0 for nowhere.
+static INTERRUPT_TARGET: AtomicI32 = AtomicI32::new(0);
+
+extern "C" fn on_sigint(_: c_int) {
+    let pid = INTERRUPT_TARGET.load(Ordering::SeqCst);
+    if pid != 0 {
+        unsafe { libckill(pid, SIGINT) };
+    }
+}
+
+/// Stops SIGINT from killing us, instead passing it on to whichever
+/// process was last given to `set_interrupt_target`.
+pub fn catch_interrupts() -> Result<()> {
+    let mut action: libc::sigaction = unsafe { std::mem::zeroed() };
+    action.sa_sigaction = on_sigint as extern "C" fn(c_int) as usize;
+    action.sa_flags = SA_RESTART;
+    errwrap(|| unsafe { sigemptyset(&mut action.sa_mask) })?;
+    errwrap(|| unsafe { libcsigaction(SIGINT, &action, std::ptr::null_mut()) })?;
+    Ok(())
+}
+
+pub fn set_interrupt_target(pid: pid_t) {
+    INTERRUPT_TARGET.store(pid, Ordering::SeqCst);
+}
+
+/// A pipe, closed on exec. `dup2` it to keep an end open in a child.
 pub fn pipe() -> Result<(File, File)> {
     let mut fds = [0 as RawFd; 2];
-    errwrap(|| unsafe { libcpipe(fds.as_mut_ptr()) })?;
+    errwrap(|| unsafe { libcpipe2(fds.as_mut_ptr(), O_CLOEXEC) })?;
     let read = unsafe { File::from_raw_fd(fds[0]) };
     let write = unsafe { File::from_raw_fd(fds[1]) };
     Ok((read, write))
@@ -108,6 +211,56 @@ pub fn dup2(from: RawFd, to: RawFd) -> Result<()> {
     Ok(())
 }
 
+/// Opens a new pseudo-terminal, returning the master and slave ends.
+pub fn openpty() -> Result<(File, File)> {
+    let master = errwrap(|| unsafe { posix_openpt(O_RDWR | O_NOCTTY | O_CLOEXEC) })?;
+    let master = unsafe { File::from_raw_fd(master) };
+    errwrap(|| unsafe { grantpt(master.as_raw_fd()) })?;
+    errwrap(|| unsafe { unlockpt(master.as_raw_fd()) })?;
+
+    let mut name = [0 as c_char; 64];
+    errwrap(|| unsafe { ptsname_r(master.as_raw_fd(), name.as_mut_ptr(), name.len()) })?;
+    let name = unsafe { CStr::from_ptr(name.as_ptr()) };
+    let slave = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(O_NOCTTY)
+        .open(OsStr::from_bytes(name.to_bytes()))?;
+    Ok((master, slave))
+}
+
+/// Stops a terminal echoing what's typed into it.
+pub fn disable_echo(fd: RawFd) -> Result<()> {
+    let mut termios: termios = unsafe { std::mem::zeroed() };
+    errwrap(|| unsafe { tcgetattr(fd, &mut termios) })?;
+    termios.c_lflag &= !ECHO;
+    errwrap(|| unsafe { tcsetattr(fd, TCSANOW, &termios) })?;
+    Ok(())
+}
+
+pub fn isatty(fd: RawFd) -> bool {
+    unsafe { libcisatty(fd) == 1 }
+}
+
+pub fn setsid() -> Result<()> {
+    errwrap(|| unsafe { libcsetsid() })?;
+    Ok(())
+}
+
+/// Makes a terminal the controlling terminal of this process, which has
+/// to be a session leader.
+pub fn set_controlling_tty(fd: RawFd) -> Result<()> {
+    errwrap(|| unsafe { ioctl(fd, TIOCSCTTY, 0) })?;
+    Ok(())
+}
+
+/// Waits for one of `fds` to be ready, or `timeout` milliseconds to pass.
+/// Returns how many are ready, 0 on timing out.
+pub fn poll(fds: &mut [pollfd], timeout: c_int) -> Result<usize> {
+    let ready = errwrap(|| unsafe { libcpoll(fds.as_mut_ptr(), fds.len() as nfds_t, timeout) })?;
+    Ok(ready as usize)
+}
+
 pub fn personality(persona: u64) -> Result<()> {
     errwrap(|| unsafe { libcpersonality(persona) })?;
     Ok(())
@@ -1,22 +1,61 @@
 use crate::result::Result;
 use crate::sys::errwrap;
 use libc::{
-    pid_t, ptrace, user_regs_struct, PTRACE_CONT, PTRACE_GETREGS, PTRACE_PEEKTEXT, PTRACE_POKETEXT,
-    PTRACE_SETREGS, PTRACE_SINGLESTEP, PTRACE_TRACEME,
+    c_ulong, pid_t, ptrace, siginfo_t, user_fpregs_struct, user_regs_struct, PTRACE_ATTACH,
+    PTRACE_CONT, PTRACE_DETACH, PTRACE_GETEVENTMSG, PTRACE_GETFPREGS, PTRACE_GETREGS,
+    PTRACE_GETSIGINFO, PTRACE_PEEKTEXT, PTRACE_PEEKUSER, PTRACE_POKETEXT, PTRACE_POKEUSER,
+    PTRACE_SETOPTIONS, PTRACE_SETREGS, PTRACE_SINGLESTEP, PTRACE_SYSCALL, PTRACE_TRACEME,
 };
 
+/// si_code reported for a SIGTRAP raised by an int3 instruction.
+pub const SI_KERNEL: i32 = 0x80;
+
+/// offsetof(struct user, u_debugreg) on x86_64.
+const DEBUGREG_OFFSET: usize = 848;
+
 pub fn traceme() -> Result<()> {
     errwrap(|| unsafe { ptrace(PTRACE_TRACEME, 0, &mut 0, 0) })?;
     Ok(())
 }
 
-pub fn singlestep(pid: pid_t) -> Result<()> {
-    errwrap(|| unsafe { ptrace(PTRACE_SINGLESTEP, pid, &mut 0, 0) })?;
+pub fn attach(pid: pid_t) -> Result<()> {
+    errwrap(|| unsafe { ptrace(PTRACE_ATTACH, pid, 0, 0) })?;
+    Ok(())
+}
+
+pub fn detach(pid: pid_t) -> Result<()> {
+    errwrap(|| unsafe { ptrace(PTRACE_DETACH, pid, 0, 0) })?;
+    Ok(())
+}
+
+pub fn setoptions(pid: pid_t, options: i32) -> Result<()> {
+    errwrap(|| unsafe { ptrace(PTRACE_SETOPTIONS, pid, 0, options) })?;
+    Ok(())
+}
+
+/// The extra information that comes with a PTRACE_EVENT_* stop, e.g. the ID
+/// of the new thread for PTRACE_EVENT_CLONE.
+pub fn geteventmsg(pid: pid_t) -> Result<c_ulong> {
+    let mut msg: c_ulong = 0;
+    errwrap(|| unsafe { ptrace(PTRACE_GETEVENTMSG, pid, 0, &mut msg) })?;
+    Ok(msg)
+}
+
+/// Executes one instruction, delivering `signal` first unless it's 0.
+pub fn singlestep(pid: pid_t, signal: i32) -> Result<()> {
+    errwrap(|| unsafe { ptrace(PTRACE_SINGLESTEP, pid, &mut 0, signal) })?;
+    Ok(())
+}
+
+/// Resumes the process, delivering `signal` first unless it's 0.
+pub fn cont(pid: pid_t, signal: i32) -> Result<()> {
+    errwrap(|| unsafe { ptrace(PTRACE_CONT, pid, &mut 0, signal) })?;
     Ok(())
 }
 
-pub fn cont(pid: pid_t) -> Result<()> {
-    errwrap(|| unsafe { ptrace(PTRACE_CONT, pid, &mut 0, 0) })?;
+/// Like `cont`, but stops again at the next syscall entry or exit.
+pub fn syscall(pid: pid_t, signal: i32) -> Result<()> {
+    errwrap(|| unsafe { ptrace(PTRACE_SYSCALL, pid, 0, signal) })?;
     Ok(())
 }
 
@@ -56,6 +95,12 @@ pub fn getregs(pid: pid_t) -> Result<user_regs_struct> {
     Ok(regs)
 }
 
+pub fn getfpregs(pid: pid_t) -> Result<user_fpregs_struct> {
+    let mut regs: user_fpregs_struct = unsafe { std::mem::zeroed() };
+    errwrap(|| unsafe { ptrace(PTRACE_GETFPREGS, pid, 0, &mut regs) })?;
+    Ok(regs)
+}
+
 pub fn setregs(pid: pid_t, regs: &user_regs_struct) -> Result<()> {
     errwrap(|| unsafe { ptrace(PTRACE_SETREGS, pid, 0, regs) })?;
     Ok(())
@@ -69,3 +114,30 @@ pub fn poke(pid: pid_t, addr: usize, data: usize) -> Result<()> {
     errwrap(|| unsafe { ptrace(PTRACE_POKETEXT, pid, addr, data) })?;
     Ok(())
 }
+
+pub fn getsiginfo(pid: pid_t) -> Result<siginfo_t> {
+    let mut siginfo: siginfo_t = unsafe { std::mem::zeroed() };
+    errwrap(|| unsafe { ptrace(PTRACE_GETSIGINFO, pid, 0, &mut siginfo) })?;
+    Ok(siginfo)
+}
+
+pub fn peekuser(pid: pid_t, offset: usize) -> Result<usize> {
+    errwrap(|| unsafe { ptrace(PTRACE_PEEKUSER, pid, offset, 0) }).map(|d| d as usize)
+}
+
+pub fn pokeuser(pid: pid_t, offset: usize, data: usize) -> Result<()> {
+    errwrap(|| unsafe { ptrace(PTRACE_POKEUSER, pid, offset, data) })?;
+    Ok(())
+}
+
+pub fn get_debugreg(pid: pid_t, n: usize) -> Result<usize> {
+    peekuser(pid, DEBUGREG_OFFSET + n * std::mem::size_of::<usize>())
+}
+
+pub fn set_debugreg(pid: pid_t, n: usize, value: usize) -> Result<()> {
+    pokeuser(
+        pid,
+        DEBUGREG_OFFSET + n * std::mem::size_of::<usize>(),
+        value,
+    )
+}
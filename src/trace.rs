@@ -0,0 +1,619 @@
+use crate::debugger::{signal, StopReason, Subordinate, SyscallStop};
+use crate::result::Result;
+use libc::{
+    CLONE_CHILD_CLEARTID, CLONE_CHILD_SETTID, CLONE_DETACHED, CLONE_FILES, CLONE_FS, CLONE_IO,
+    CLONE_NEWCGROUP, CLONE_NEWIPC, CLONE_NEWNET, CLONE_NEWNS, CLONE_NEWPID, CLONE_NEWUSER,
+    CLONE_NEWUTS, CLONE_PARENT, CLONE_PARENT_SETTID, CLONE_PTRACE, CLONE_SETTLS, CLONE_SIGHAND,
+    CLONE_SYSVSEM, CLONE_THREAD, CLONE_UNTRACED, CLONE_VFORK, CLONE_VM, MAP_ANONYMOUS,
+    MAP_DENYWRITE, MAP_FIXED, MAP_GROWSDOWN, MAP_HUGETLB, MAP_LOCKED, MAP_NORESERVE, MAP_POPULATE,
+    MAP_PRIVATE, MAP_SHARED, MAP_STACK, O_APPEND, O_ASYNC, O_CLOEXEC, O_CREAT, O_DIRECT,
+    O_DIRECTORY, O_DSYNC, O_EXCL, O_LARGEFILE, O_NOATIME, O_NOCTTY, O_NOFOLLOW, O_NONBLOCK, O_PATH,
+    O_RDWR, O_SYNC, O_TMPFILE, O_TRUNC, O_WRONLY, PROT_EXEC, PROT_READ, PROT_WRITE, R_OK, W_OK,
+    X_OK,
+};
+use std::collections::HashMap;
+
+/// How much of a string or buffer to show before cutting it off.
+const MAX_STRING: usize = 32;
+/// How many entries of an argv style array to show.
+const MAX_ARRAY: usize = 32;
+const AT_FDCWD: i32 = -100;
+
+/// How to show a syscall argument.
+#[derive(Clone, Copy)]
+enum Arg {
+    /// Whatever looks best, as for `SyscallStop`'s Display.
+    Any,
+    Int,
+    Fd,
+    /// A directory fd for the *at syscalls, which may be AT_FDCWD.
+    DirFd,
+    Ptr,
+    Octal,
+    /// A NUL terminated string.
+    Str,
+    /// A NULL terminated array of strings, e.g. argv.
+    Strs,
+    /// A buffer the syscall reads from, its length in the given argument.
+    InBuf(usize),
+    /// A buffer the syscall fills in, its length in the return value.
+    OutBuf,
+    OpenFlags,
+    Prot,
+    MapFlags,
+    AccessMode,
+    /// clone's flags, with the signal to send the parent in the low byte.
+    CloneFlags,
+    /// A pointer to clone3's arguments.
+    CloneArgs,
+}
+
+/// Runs the subordinate to completion, printing every syscall it makes to
+/// stderr like strace.
+pub struct Tracer {
+    subordinate: Subordinate,
+    // Arguments of syscalls we've seen the entry to but not the exit, by
+    // thread. They're read on entry as an exec replaces the memory they're in.
+    entered: HashMap<i32, Vec<Option<String>>>,
+}
+
+impl Tracer {
+    pub fn new(subordinate: Subordinate) -> Self {
+        Self {
+            subordinate,
+            entered: HashMap::new(),
+        }
+    }
+
+    /// Returns the exit status of the subordinate.
+    pub fn start(&mut self) -> Result<i32> {
+        self.subordinate.catch_syscalls(vec![])?;
+
+        while self.subordinate.is_alive() {
+            self.subordinate.cont()?;
+            if let Some(stop) = self.subordinate.syscall_stop().cloned() {
+                self.print_syscall(&stop);
+            }
+        }
+
+        // Anything still here never returned, like exit_group.
+        for args in self.entered.values() {
+            eprintln!("{} = ?", join(args));
+        }
+
+        match self.subordinate.stop_reason() {
+            StopReason::Exited(status) => {
+                eprintln!("+++ exited with {} +++", status);
+                Ok(status)
+            }
+            // Exit the way a shell reports a program killed by a signal.
+            StopReason::Killed(sig) => {
+                eprintln!("+++ killed by {} +++", signal::name(sig));
+                Ok(128 + sig)
+            }
+            _ => {
+                eprintln!("+++ killed +++");
+                Ok(1)
+            }
+        }
+    }
+
+    fn print_syscall(&mut self, stop: &SyscallStop) {
+        let tid = self.current_tid();
+        let signature = signature(&stop.name());
+
+        let ret = match stop.ret {
+            Some(ret) => ret,
+            None => {
+                let mut args = vec![Some(stop.name())];
+                for (i, arg) in stop.args().iter().enumerate() {
+                    let kind = signature.get(i).copied().unwrap_or(Arg::Any);
+                    args.push(match kind {
+                        Arg::OutBuf => None,
+                        kind => Some(self.format_arg(kind, *arg, stop)),
+                    });
+                }
+                self.entered.insert(tid, args);
+                return;
+            }
+        };
+
+        let mut args = match self.entered.remove(&tid) {
+            Some(args) => args,
+            // We started tracing in the middle of this one.
+            None => return,
+        };
+        for (i, arg) in args.iter_mut().skip(1).enumerate() {
+            if arg.is_none() {
+                *arg = Some(self.format_out_buf(stop.args[i], ret));
+            }
+        }
+
+        let ret = match ret {
+            errno if errno < 0 && errno > -4096 => match errno_name(-errno as i32) {
+                Some(name) => format!("-1 {}", name),
+                None => format!("-1 errno {}", -errno),
+            },
+            ret if ["mmap", "mremap", "brk"].contains(&stop.name().as_str()) => {
+                format!("0x{:x}", ret)
+            }
+            ret => ret.to_string(),
+        };
+        eprintln!("{} = {}", join(&args), ret);
+    }
+
+    fn format_arg(&self, kind: Arg, arg: u64, stop: &SyscallStop) -> String {
+        match kind {
+            Arg::Any => {
+                let signed = arg as i64;
+                if signed > -4096 && signed < 0x10000 {
+                    signed.to_string()
+                } else {
+                    format!("0x{:x}", arg)
+                }
+            }
+            Arg::Int => (arg as i64).to_string(),
+            Arg::Fd => (arg as i32).to_string(),
+            Arg::DirFd if arg as i32 == AT_FDCWD => "AT_FDCWD".to_owned(),
+            Arg::DirFd => (arg as i32).to_string(),
+            Arg::Ptr if arg == 0 => "NULL".to_owned(),
+            Arg::Ptr => format!("0x{:x}", arg),
+            Arg::Octal if arg == 0 => "0".to_owned(),
+            Arg::Octal => format!("0{:o}", arg),
+            Arg::Str => self.format_str(arg),
+            Arg::Strs => self.format_strs(arg),
+            Arg::InBuf(len) => self.format_buf(arg, stop.args[len] as usize),
+            Arg::OutBuf => self.format_arg(Arg::Ptr, arg, stop),
+            Arg::OpenFlags => {
+                let mode = match arg as i32 & 0b11 {
+                    O_WRONLY => "O_WRONLY",
+                    O_RDWR => "O_RDWR",
+                    _ => "O_RDONLY",
+                };
+                if arg & !0b11 == 0 {
+                    return mode.to_owned();
+                }
+                let rest = format_flags(
+                    arg & !0b11,
+                    &[
+                        (O_CREAT, "O_CREAT"),
+                        (O_EXCL, "O_EXCL"),
+                        (O_NOCTTY, "O_NOCTTY"),
+                        (O_TRUNC, "O_TRUNC"),
+                        (O_APPEND, "O_APPEND"),
+                        (O_NONBLOCK, "O_NONBLOCK"),
+                        // O_SYNC includes the O_DSYNC bit, so it has to come first.
+                        (O_SYNC, "O_SYNC"),
+                        (O_DSYNC, "O_DSYNC"),
+                        (O_ASYNC, "O_ASYNC"),
+                        (O_DIRECT, "O_DIRECT"),
+                        (O_LARGEFILE, "O_LARGEFILE"),
+                        // Same again with O_TMPFILE and O_DIRECTORY.
+                        (O_TMPFILE, "O_TMPFILE"),
+                        (O_DIRECTORY, "O_DIRECTORY"),
+                        (O_NOFOLLOW, "O_NOFOLLOW"),
+                        (O_NOATIME, "O_NOATIME"),
+                        (O_CLOEXEC, "O_CLOEXEC"),
+                        (O_PATH, "O_PATH"),
+                    ],
+                );
+                format!("{}|{}", mode, rest)
+            }
+            Arg::Prot if arg == 0 => "PROT_NONE".to_owned(),
+            Arg::Prot => format_flags(
+                arg,
+                &[
+                    (PROT_READ, "PROT_READ"),
+                    (PROT_WRITE, "PROT_WRITE"),
+                    (PROT_EXEC, "PROT_EXEC"),
+                ],
+            ),
+            Arg::MapFlags => format_flags(
+                arg,
+                &[
+                    (MAP_SHARED, "MAP_SHARED"),
+                    (MAP_PRIVATE, "MAP_PRIVATE"),
+                    (MAP_FIXED, "MAP_FIXED"),
+                    (MAP_ANONYMOUS, "MAP_ANONYMOUS"),
+                    (MAP_GROWSDOWN, "MAP_GROWSDOWN"),
+                    (MAP_DENYWRITE, "MAP_DENYWRITE"),
+                    (MAP_LOCKED, "MAP_LOCKED"),
+                    (MAP_NORESERVE, "MAP_NORESERVE"),
+                    (MAP_POPULATE, "MAP_POPULATE"),
+                    (MAP_STACK, "MAP_STACK"),
+                    (MAP_HUGETLB, "MAP_HUGETLB"),
+                ],
+            ),
+            Arg::AccessMode if arg == 0 => "F_OK".to_owned(),
+            Arg::AccessMode => format_flags(arg, &[(R_OK, "R_OK"), (W_OK, "W_OK"), (X_OK, "X_OK")]),
+            Arg::CloneFlags => match (arg & !0xff, (arg & 0xff) as i32) {
+                (flags, 0) => format_clone_flags(flags),
+                (0, sig) => signal::name(sig),
+                (flags, sig) => format!("{}|{}", format_clone_flags(flags), signal::name(sig)),
+            },
+            Arg::CloneArgs => self.format_clone_args(arg),
+        }
+    }
+
+    /// Shows the struct clone_args that clone3 takes, or what we need of it.
+    fn format_clone_args(&self, addr: u64) -> String {
+        let bytes = match self.subordinate.read_bytes(addr as usize, 64) {
+            Ok(bytes) => bytes,
+            Err(_) => return format!("0x{:x}", addr),
+        };
+        let field = |i: usize| {
+            let mut word = [0; 8];
+            word.copy_from_slice(&bytes[i * 8..i * 8 + 8]);
+            u64::from_ne_bytes(word)
+        };
+        let exit_signal = match field(4) {
+            0 => "0".to_owned(),
+            sig => signal::name(sig as i32),
+        };
+        format!(
+            "{{flags={}, exit_signal={}, stack=0x{:x}, stack_size=0x{:x}, tls=0x{:x}}}",
+            format_clone_flags(field(0)),
+            exit_signal,
+            field(5),
+            field(6),
+            field(7)
+        )
+    }
+
+    fn format_out_buf(&self, addr: u64, ret: i64) -> String {
+        if ret < 0 {
+            return format!("0x{:x}", addr);
+        }
+        self.format_buf(addr, ret as usize)
+    }
+
+    fn format_buf(&self, addr: u64, len: usize) -> String {
+        if addr == 0 {
+            return "NULL".to_owned();
+        }
+        match self
+            .subordinate
+            .read_bytes(addr as usize, len.min(MAX_STRING))
+        {
+            Ok(bytes) => quote(&bytes, len > MAX_STRING),
+            Err(_) => format!("0x{:x}", addr),
+        }
+    }
+
+    fn format_str(&self, addr: u64) -> String {
+        if addr == 0 {
+            return "NULL".to_owned();
+        }
+        match self.read_str(addr as usize) {
+            Ok((bytes, truncated)) => quote(&bytes, truncated),
+            Err(_) => format!("0x{:x}", addr),
+        }
+    }
+
+    fn format_strs(&self, addr: u64) -> String {
+        if addr == 0 {
+            return "NULL".to_owned();
+        }
+
+        let mut strs = Vec::new();
+        let wordlen = std::mem::size_of::<usize>();
+        for i in 0..=MAX_ARRAY {
+            let ptr = match self.subordinate.peek(addr as usize + i * wordlen) {
+                Ok(ptr) => ptr,
+                Err(_) => return format!("0x{:x}", addr),
+            };
+            if ptr == 0 {
+                break;
+            }
+            if i == MAX_ARRAY {
+                strs.push("...".to_owned());
+                break;
+            }
+            strs.push(self.format_str(ptr as u64));
+        }
+        format!("[{}]", strs.join(", "))
+    }
+
+    /// Reads up to `MAX_STRING` bytes of a NUL terminated string, and
+    /// whether there was more to it.
+    fn read_str(&self, addr: usize) -> Result<(Vec<u8>, bool)> {
+        let mut bytes = Vec::new();
+        let wordlen = std::mem::size_of::<usize>();
+        // Stick to aligned words, so we don't read over the end of a page
+        // unless the string does.
+        let mut word_addr = addr - addr % wordlen;
+        let mut skip = addr % wordlen;
+        loop {
+            let word = self.subordinate.peek(word_addr)?.to_ne_bytes();
+            for &byte in &word[skip..] {
+                if byte == 0 {
+                    return Ok((bytes, false));
+                }
+                if bytes.len() == MAX_STRING {
+                    return Ok((bytes, true));
+                }
+                bytes.push(byte);
+            }
+            word_addr += wordlen;
+            skip = 0;
+        }
+    }
+
+    fn current_tid(&self) -> i32 {
+        let id = self.subordinate.current_thread();
+        self.subordinate
+            .threads()
+            .iter()
+            .find(|thread| thread.id == id)
+            .map_or(self.subordinate.pid(), |thread| thread.tid)
+    }
+}
+
+fn join(args: &[Option<String>]) -> String {
+    let name = args[0].as_deref().unwrap_or_default();
+    let args: Vec<&str> = args[1..]
+        .iter()
+        .map(|arg| arg.as_deref().unwrap_or("?"))
+        .collect();
+    format!("{}({})", name, args.join(", "))
+}
+
+/// Quotes bytes as a C string literal.
+fn quote(bytes: &[u8], truncated: bool) -> String {
+    let mut quoted = String::from("\"");
+    for &byte in bytes {
+        match byte {
+            b'\n' => quoted.push_str("\\n"),
+            b'\t' => quoted.push_str("\\t"),
+            b'\r' => quoted.push_str("\\r"),
+            b'"' => quoted.push_str("\\\""),
+            b'\\' => quoted.push_str("\\\\"),
+            0x20..=0x7e => quoted.push(byte as char),
+            _ => quoted.push_str(&format!("\\x{:02x}", byte)),
+        }
+    }
+    quoted.push('"');
+    if truncated {
+        quoted.push_str("...");
+    }
+    quoted
+}
+
+/// Shows `value` as the names of the flags set in it, OR'd together, with
+/// any bits we don't have a name for on the end in hex.
+fn format_flags(value: u64, flags: &[(i32, &str)]) -> String {
+    let mut names = Vec::new();
+    let mut rest = value;
+    for &(flag, name) in flags {
+        // Some flags, like CLONE_IO, have the sign bit of an int set.
+        let flag = flag as u32 as u64;
+        if flag != 0 && rest & flag == flag {
+            names.push(name.to_owned());
+            rest &= !flag;
+        }
+    }
+    if rest != 0 || names.is_empty() {
+        names.push(format!("0x{:x}", rest));
+    }
+    names.join("|")
+}
+
+fn format_clone_flags(flags: u64) -> String {
+    format_flags(
+        flags,
+        &[
+            (CLONE_VM, "CLONE_VM"),
+            (CLONE_FS, "CLONE_FS"),
+            (CLONE_FILES, "CLONE_FILES"),
+            (CLONE_SIGHAND, "CLONE_SIGHAND"),
+            (CLONE_PTRACE, "CLONE_PTRACE"),
+            (CLONE_VFORK, "CLONE_VFORK"),
+            (CLONE_PARENT, "CLONE_PARENT"),
+            (CLONE_THREAD, "CLONE_THREAD"),
+            (CLONE_NEWNS, "CLONE_NEWNS"),
+            (CLONE_SYSVSEM, "CLONE_SYSVSEM"),
+            (CLONE_SETTLS, "CLONE_SETTLS"),
+            (CLONE_PARENT_SETTID, "CLONE_PARENT_SETTID"),
+            (CLONE_CHILD_CLEARTID, "CLONE_CHILD_CLEARTID"),
+            (CLONE_DETACHED, "CLONE_DETACHED"),
+            (CLONE_UNTRACED, "CLONE_UNTRACED"),
+            (CLONE_CHILD_SETTID, "CLONE_CHILD_SETTID"),
+            (CLONE_NEWCGROUP, "CLONE_NEWCGROUP"),
+            (CLONE_NEWUTS, "CLONE_NEWUTS"),
+            (CLONE_NEWIPC, "CLONE_NEWIPC"),
+            (CLONE_NEWUSER, "CLONE_NEWUSER"),
+            (CLONE_NEWPID, "CLONE_NEWPID"),
+            (CLONE_NEWNET, "CLONE_NEWNET"),
+            (CLONE_IO, "CLONE_IO"),
+        ],
+    )
+}
+
+/// How to show the arguments of the syscalls we know something about.
+/// Anything missing is shown as `Arg::Any`.
+fn signature(name: &str) -> &'static [Arg] {
+    use Arg::*;
+    match name {
+        "read" | "pread64" => &[Fd, OutBuf, Int, Int],
+        "write" | "pwrite64" => &[Fd, InBuf(2), Int, Int],
+        "open" => &[Str, OpenFlags, Octal],
+        "openat" => &[DirFd, Str, OpenFlags, Octal],
+        "creat" | "mkdir" | "chmod" => &[Str, Octal],
+        "mkdirat" | "fchmodat" => &[DirFd, Str, Octal],
+        "close" | "dup" | "fsync" | "fdatasync" | "fchdir" => &[Fd],
+        "dup2" | "dup3" => &[Fd, Fd, OpenFlags],
+        "lseek" => &[Fd, Int, Int],
+        "ioctl" | "fcntl" | "fstat" | "fstatfs" | "getdents64" => &[Fd],
+        "stat" | "lstat" | "statfs" | "truncate" => &[Str, Ptr],
+        "newfstatat" | "statx" => &[DirFd, Str],
+        "access" => &[Str, AccessMode],
+        "faccessat" | "faccessat2" => &[DirFd, Str, AccessMode],
+        "chdir" | "rmdir" | "unlink" | "chroot" => &[Str],
+        "unlinkat" => &[DirFd, Str],
+        "rename" | "link" | "symlink" => &[Str, Str],
+        "renameat" | "renameat2" | "linkat" => &[DirFd, Str, DirFd, Str],
+        "readlink" => &[Str, OutBuf, Int],
+        "readlinkat" => &[DirFd, Str, OutBuf, Int],
+        "getcwd" => &[OutBuf, Int],
+        "mmap" => &[Ptr, Int, Prot, MapFlags, Fd, Any],
+        "mprotect" => &[Ptr, Int, Prot],
+        "munmap" => &[Ptr, Int],
+        "brk" => &[Ptr],
+        "execve" => &[Str, Strs, Ptr],
+        "execveat" => &[DirFd, Str, Strs, Ptr],
+        "exit" | "exit_group" => &[Int],
+        "clone" => &[CloneFlags, Ptr, Ptr, Ptr, Ptr],
+        "clone3" => &[CloneArgs, Int],
+        _ => &[],
+    }
+}
+
+fn errno_name(errno: i32) -> Option<&'static str> {
+    ERRNOS.iter().find(|e| e.0 == errno).map(|e| e.1)
+}
+
+const ERRNOS: &[(i32, &str)] = &[
+    (libc::EPERM, "EPERM"),
+    (libc::ENOENT, "ENOENT"),
+    (libc::ESRCH, "ESRCH"),
+    (libc::EINTR, "EINTR"),
+    (libc::EIO, "EIO"),
+    (libc::ENXIO, "ENXIO"),
+    (libc::E2BIG, "E2BIG"),
+    (libc::ENOEXEC, "ENOEXEC"),
+    (libc::EBADF, "EBADF"),
+    (libc::ECHILD, "ECHILD"),
+    (libc::EAGAIN, "EAGAIN"),
+    (libc::ENOMEM, "ENOMEM"),
+    (libc::EACCES, "EACCES"),
+    (libc::EFAULT, "EFAULT"),
+    (libc::ENOTBLK, "ENOTBLK"),
+    (libc::EBUSY, "EBUSY"),
+    (libc::EEXIST, "EEXIST"),
+    (libc::EXDEV, "EXDEV"),
+    (libc::ENODEV, "ENODEV"),
+    (libc::ENOTDIR, "ENOTDIR"),
+    (libc::EISDIR, "EISDIR"),
+    (libc::EINVAL, "EINVAL"),
+    (libc::ENFILE, "ENFILE"),
+    (libc::EMFILE, "EMFILE"),
+    (libc::ENOTTY, "ENOTTY"),
+    (libc::ETXTBSY, "ETXTBSY"),
+    (libc::EFBIG, "EFBIG"),
+    (libc::ENOSPC, "ENOSPC"),
+    (libc::ESPIPE, "ESPIPE"),
+    (libc::EROFS, "EROFS"),
+    (libc::EMLINK, "EMLINK"),
+    (libc::EPIPE, "EPIPE"),
+    (libc::EDOM, "EDOM"),
+    (libc::ERANGE, "ERANGE"),
+    (libc::EDEADLK, "EDEADLK"),
+    (libc::ENAMETOOLONG, "ENAMETOOLONG"),
+    (libc::ENOLCK, "ENOLCK"),
+    (libc::ENOSYS, "ENOSYS"),
+    (libc::ENOTEMPTY, "ENOTEMPTY"),
+    (libc::ELOOP, "ELOOP"),
+    (libc::ENOMSG, "ENOMSG"),
+    (libc::EIDRM, "EIDRM"),
+    (libc::ENOSTR, "ENOSTR"),
+    (libc::ENODATA, "ENODATA"),
+    (libc::ETIME, "ETIME"),
+    (libc::ENOSR, "ENOSR"),
+    (libc::ENOLINK, "ENOLINK"),
+    (libc::EPROTO, "EPROTO"),
+    (libc::EBADMSG, "EBADMSG"),
+    (libc::EOVERFLOW, "EOVERFLOW"),
+    (libc::EILSEQ, "EILSEQ"),
+    (libc::EUSERS, "EUSERS"),
+    (libc::ENOTSOCK, "ENOTSOCK"),
+    (libc::EDESTADDRREQ, "EDESTADDRREQ"),
+    (libc::EMSGSIZE, "EMSGSIZE"),
+    (libc::EPROTOTYPE, "EPROTOTYPE"),
+    (libc::ENOPROTOOPT, "ENOPROTOOPT"),
+    (libc::EPROTONOSUPPORT, "EPROTONOSUPPORT"),
+    (libc::ESOCKTNOSUPPORT, "ESOCKTNOSUPPORT"),
+    (libc::EOPNOTSUPP, "EOPNOTSUPP"),
+    (libc::EPFNOSUPPORT, "EPFNOSUPPORT"),
+    (libc::EAFNOSUPPORT, "EAFNOSUPPORT"),
+    (libc::EADDRINUSE, "EADDRINUSE"),
+    (libc::EADDRNOTAVAIL, "EADDRNOTAVAIL"),
+    (libc::ENETDOWN, "ENETDOWN"),
+    (libc::ENETUNREACH, "ENETUNREACH"),
+    (libc::ENETRESET, "ENETRESET"),
+    (libc::ECONNABORTED, "ECONNABORTED"),
+    (libc::ECONNRESET, "ECONNRESET"),
+    (libc::ENOBUFS, "ENOBUFS"),
+    (libc::EISCONN, "EISCONN"),
+    (libc::ENOTCONN, "ENOTCONN"),
+    (libc::ESHUTDOWN, "ESHUTDOWN"),
+    (libc::ETOOMANYREFS, "ETOOMANYREFS"),
+    (libc::ETIMEDOUT, "ETIMEDOUT"),
+    (libc::ECONNREFUSED, "ECONNREFUSED"),
+    (libc::EHOSTDOWN, "EHOSTDOWN"),
+    (libc::EHOSTUNREACH, "EHOSTUNREACH"),
+    (libc::EALREADY, "EALREADY"),
+    (libc::EINPROGRESS, "EINPROGRESS"),
+    (libc::ESTALE, "ESTALE"),
+    (libc::EDQUOT, "EDQUOT"),
+    (libc::ECANCELED, "ECANCELED"),
+    (libc::EOWNERDEAD, "EOWNERDEAD"),
+    (libc::ENOTRECOVERABLE, "ENOTRECOVERABLE"),
+    // Only seen by the tracer, for a syscall interrupted by a signal.
+    (512, "ERESTARTSYS"),
+    (513, "ERESTARTNOINTR"),
+    (514, "ERESTARTNOHAND"),
+    (516, "ERESTART_RESTARTBLOCK"),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags() {
+        assert_eq!(
+            format_flags(0x3, &[(PROT_READ, "PROT_READ"), (PROT_WRITE, "PROT_WRITE")]),
+            "PROT_READ|PROT_WRITE"
+        );
+        assert_eq!(
+            format_flags(0x9, &[(PROT_READ, "PROT_READ")]),
+            "PROT_READ|0x8"
+        );
+        assert_eq!(format_flags(0, &[(PROT_READ, "PROT_READ")]), "0x0");
+        assert_eq!(
+            format_flags(O_SYNC as u64, &[(O_SYNC, "O_SYNC"), (O_DSYNC, "O_DSYNC")]),
+            "O_SYNC"
+        );
+    }
+
+    #[test]
+    fn clone_flags() {
+        assert_eq!(
+            format_clone_flags((CLONE_VM | CLONE_THREAD) as u64),
+            "CLONE_VM|CLONE_THREAD"
+        );
+        // The sign bit of an int, which mustn't spread to the upper half.
+        assert_eq!(format_clone_flags(0x8000_0000), "CLONE_IO");
+        assert_eq!(format_clone_flags(0x1_0000_0000), "0x100000000");
+    }
+
+    #[test]
+    fn quoting() {
+        assert_eq!(quote(b"hi\n\t\"\\", false), r#""hi\n\t\"\\""#);
+        assert_eq!(quote(&[0, 0x7f, b'a'], true), r#""\x00\x7fa"..."#);
+    }
+
+    #[test]
+    fn errnos() {
+        assert_eq!(errno_name(libc::ENOENT), Some("ENOENT"));
+        assert_eq!(errno_name(512), Some("ERESTARTSYS"));
+        assert_eq!(errno_name(4000), None);
+        for (i, (errno, name)) in ERRNOS.iter().enumerate() {
+            assert!(
+                ERRNOS[..i].iter().all(|e| e.0 != *errno),
+                "{} is in the table twice",
+                name
+            );
+        }
+    }
+}
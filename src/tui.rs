@@ -22,7 +22,7 @@ use unicode_width::UnicodeWidthStr;
 
 use iced_x86::{Decoder, DecoderOptions, Formatter, Instruction, NasmFormatter};
 
-use crate::debugger::Subordinate;
+use crate::debugger::{Location, StopReason, Subordinate};
 use crate::result::Result;
 
 pub enum Event<I> {
@@ -68,17 +68,12 @@ impl Events {
             let ignore_exit_key = ignore_exit_key.clone();
             thread::spawn(move || {
                 let stdin = io::stdin();
-                for evt in stdin.keys() {
-                    match evt {
-                        Ok(key) => {
-                            if let Err(_) = tx.send(Event::Input(key)) {
-                                return;
-                            }
-                            if !ignore_exit_key.load(Ordering::Relaxed) && key == config.exit_key {
-                                return;
-                            }
-                        }
-                        Err(_) => {}
+                for key in stdin.keys().flatten() {
+                    if tx.send(Event::Input(key)).is_err() {
+                        return;
+                    }
+                    if !ignore_exit_key.load(Ordering::Relaxed) && key == config.exit_key {
+                        return;
                     }
                 }
             })
@@ -191,8 +186,7 @@ impl Tui {
                 };
 
                 let left_text = [Text::raw(registers_s)];
-                let left_para =
-                    Paragraph::new(left_text.iter()).block(block.clone().title("Registers"));
+                let left_para = Paragraph::new(left_text.iter()).block(block.title("Registers"));
                 f.render_widget(left_para, left);
 
                 let disassembly_s = match disassemble(&self.subordinate) {
@@ -205,7 +199,7 @@ impl Tui {
 
                 let middle_text = [Text::raw(disassembly_s)];
                 let middle_para =
-                    Paragraph::new(middle_text.iter()).block(block.clone().title("Disassembly"));
+                    Paragraph::new(middle_text.iter()).block(block.title("Disassembly"));
                 f.render_widget(middle_para, middle);
 
                 let stack_s = match stack(&self.subordinate) {
@@ -217,26 +211,25 @@ impl Tui {
                 };
 
                 let right_text = [Text::raw(stack_s)];
-                let right_para =
-                    Paragraph::new(right_text.iter()).block(block.clone().title("Stack"));
+                let right_para = Paragraph::new(right_text.iter()).block(block.title("Stack"));
                 f.render_widget(right_para, right);
 
                 let bottom_left_text = [Text::raw(String::from_utf8_lossy(&self.command_output))];
                 let bottom_left_para = Paragraph::new(bottom_left_text.iter())
                     .wrap(true)
-                    .block(block.clone().title("Command output"));
+                    .block(block.title("Command output"));
                 f.render_widget(bottom_left_para, bottom_left);
 
                 let bottom_right_text = [Text::raw(String::from_utf8_lossy(&self.program_output))];
                 let bottom_right_para = Paragraph::new(bottom_right_text.iter())
                     .wrap(true)
-                    .block(block.clone().title("Program output"));
+                    .block(block.title("Program output"));
                 f.render_widget(bottom_right_para, bottom_right);
 
                 let text = [Text::raw(&self.input)];
                 let input = Paragraph::new(text.iter())
                     .style(Style::default().fg(Color::Yellow))
-                    .block(block.clone().title("Prompt"));
+                    .block(block.title("Prompt"));
                 f.render_widget(input, bottom);
             })?;
 
@@ -251,13 +244,19 @@ impl Tui {
             io::stdout().flush().ok();
 
             // Handle input
-            match events.next()? {
-                Event::Input(input) => match input {
+            let event = events.next()?;
+            // Anything written while we were drawing or running a command.
+            let output = self.subordinate.take_output();
+            self.program_output.extend(output);
+            if let Event::Input(input) = event {
+                match input {
                     Key::Char('\n') => {
                         let cmd: String = self.input.drain(..).collect();
-                        if let Err(e) =
-                            execute_command(&mut self.subordinate, cmd.split_whitespace().collect())
-                        {
+                        if let Err(e) = execute_command(
+                            &mut self.subordinate,
+                            cmd.split_whitespace().collect(),
+                            &mut self.command_output,
+                        ) {
                             writeln!(&mut self.command_output, "{}", e)?;
                         }
                     }
@@ -271,8 +270,7 @@ impl Tui {
                         break;
                     }
                     _ => {}
-                },
-                _ => {}
+                }
             }
         }
         Ok(())
@@ -281,11 +279,10 @@ impl Tui {
 
 fn disassemble(subordinate: &Subordinate) -> Result<String> {
     let regs = subordinate.registers();
-    let debug_info = subordinate.debug_info();
-    let symbol = debug_info.symbol_for_pc(regs.rip as usize);
+    let symbol = subordinate.symbol_for_addr(regs.rip as usize);
 
     let (rip, bytes) = match symbol {
-        Some(symbol) => (symbol.low_pc as u64, subordinate.instructions(symbol)?),
+        Some(symbol) => (symbol.value, subordinate.instructions(symbol)?),
         None => (regs.rip, subordinate.read_bytes(regs.rip as usize, 64)?),
     };
 
@@ -355,34 +352,64 @@ fn stack(subordinate: &Subordinate) -> Result<String> {
     Ok(String::from_utf8_lossy(ret.as_slice()).to_string())
 }
 
-fn execute_command(subordinate: &mut Subordinate, cmd: Vec<&str>) -> Result<()> {
-    match cmd.as_slice() {
+fn execute_command(
+    subordinate: &mut Subordinate,
+    cmd: Vec<&str>,
+    output: &mut Vec<u8>,
+) -> Result<()> {
+    let reason = match cmd.as_slice() {
+        ["si"] | ["stepi"] => subordinate.stepi()?,
         ["s"] | ["step"] => subordinate.step()?,
         ["c"] | ["cont"] => subordinate.cont()?,
-        ["b", addr] | ["break", addr] => set_breakpoint(subordinate, addr)?,
+        ["b", addr] | ["break", addr] => return set_breakpoint(subordinate, addr),
         other => return Err(format!("unknown command `{:?}`", other).into()),
     };
 
-    Ok(())
+    write_stop(subordinate, &reason, output)
 }
 
-fn set_breakpoint(subordinate: &mut Subordinate, addr: &str) -> Result<()> {
-    if let Ok(addr) = usize::from_str_radix(addr, 16) {
-        return subordinate.breakpoint(addr);
+/// Says why the subordinate stopped, where, and the source line if we have it.
+fn write_stop(subordinate: &Subordinate, reason: &StopReason, output: &mut Vec<u8>) -> Result<()> {
+    if reason.is_exit() {
+        writeln!(output, "process {}", reason)?;
+        return Ok(());
     }
 
-    let fetch = {
-        let symbols = subordinate.debug_info().symbols();
-        symbols.get(addr).cloned()
-    };
+    let rip = subordinate.registers().rip as usize;
+    match subordinate.symbol_for_addr(rip) {
+        Some(symbol) => writeln!(
+            output,
+            "{} at 0x{:x} in {}+{}",
+            reason,
+            rip,
+            symbol.name,
+            rip - symbol.value as usize
+        )?,
+        None => writeln!(output, "{} at 0x{:x}", reason, rip)?,
+    }
 
-    if let Some(symbol) = fetch {
-        return subordinate.breakpoint(symbol.low_pc as usize);
+    if let Some(line_info) = subordinate.line_for_pc(rip) {
+        let source = subordinate
+            .debug_info()
+            .lines(&line_info.path)
+            .and_then(|lines| {
+                let index = (line_info.line as usize).checked_sub(1)?;
+                lines.get(index).cloned()
+            });
+        match source {
+            Some(source) => writeln!(output, "{}\t{}", line_info.line, source)?,
+            None => writeln!(output, "{}:{}", line_info.path.display(), line_info.line)?,
+        }
+    }
+    Ok(())
+}
+
+fn set_breakpoint(subordinate: &mut Subordinate, location: &str) -> Result<()> {
+    if let Ok(addr) = usize::from_str_radix(location, 16) {
+        subordinate.breakpoint(&Location::Address(addr))?;
+        return Ok(());
     }
 
-    Err(format!(
-        "couldn't set breakpoint on `{}`, not a known address or symbol",
-        addr
-    )
-    .into())
+    subordinate.breakpoint(&Location::parse(location)?)?;
+    Ok(())
 }
@@ -0,0 +1,22 @@
+#include <pthread.h>
+#include <stdio.h>
+
+int counter = 0;
+
+void work(int n) { __atomic_add_fetch(&counter, n, __ATOMIC_SEQ_CST); }
+
+void *worker(void *arg) {
+	for (int i = 0; i < 3; i++)
+		work((int)(long)arg);
+	return NULL;
+}
+
+int main(void) {
+	pthread_t threads[4];
+	for (long i = 0; i < 4; i++)
+		pthread_create(&threads[i], NULL, worker, (void *)i);
+	for (int i = 0; i < 4; i++)
+		pthread_join(threads[i], NULL);
+	printf("counter %d\n", counter);
+	return 0;
+}
@@ -0,0 +1,69 @@
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn compile(fixture: &str) -> PathBuf {
+    let source = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(fixture)
+        .with_extension("c");
+    let binary = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(fixture);
+    let status = Command::new("cc")
+        .args(["-g", "-O0", "-pthread", "-o"])
+        .arg(&binary)
+        .arg(&source)
+        .status()
+        .expect("couldn't run cc");
+    assert!(status.success(), "couldn't compile {}", fixture);
+    binary
+}
+
+/// Runs the debugger on `program`, typing `commands` at it, and returns
+/// what it printed.
+fn debug(program: &PathBuf, commands: &str) -> String {
+    let mut debugger = Command::new(env!("CARGO_BIN_EXE_rust-debugger"))
+        .arg(program)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("couldn't start the debugger");
+    debugger
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(commands.as_bytes())
+        .unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(30);
+    while debugger.try_wait().unwrap().is_none() {
+        if Instant::now() > deadline {
+            debugger.kill().unwrap();
+            panic!("the debugger got stuck");
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    let mut output = String::new();
+    debugger
+        .stdout
+        .take()
+        .unwrap()
+        .read_to_string(&mut output)
+        .unwrap();
+    output
+}
+
+#[test]
+fn breakpoint_hit_by_every_thread() {
+    let program = compile("threads");
+    let mut commands = String::from("b work\n");
+    for _ in 0..13 {
+        commands.push_str("c\n");
+    }
+    commands.push_str("q\ny\n");
+
+    let output = debug(&program, &commands);
+    assert_eq!(output.matches("breakpoint 1 hit").count(), 12, "{}", output);
+}